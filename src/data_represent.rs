@@ -1,665 +1,6976 @@
+use crate::error::Error;
 use by_address::ByAddress;
+use chrono::{DateTime, Duration, NaiveDateTime};
+use indexmap::{IndexMap, IndexSet};
+use rand::{rngs::StdRng, RngExt, SeedableRng};
+#[cfg(feature = "decimal")]
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use std::cell::RefCell;
 use std::cmp::{Ordering, PartialOrd};
-use std::collections::{hash_map::DefaultHasher, HashMap, HashSet};
+use std::collections::hash_map::{DefaultHasher, Entry};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::error;
+use std::fmt;
 use std::hash::{Hash, Hasher};
+use std::io;
+use std::iter::zip;
+use std::sync::Arc;
 
 /// Record's attributes, with it's type auto detected:
-///   if it can be parsed as a int, then it's type is i32
+///   if it can be parsed as a int, then it's type is i32, or i64 if it's too large for i32
 ///   else if it can be parsed as a float, then it's type is f32
 ///   else if it is `true` or `false`, then it's type is bool
 ///   else it is raw String
 ///
-/// Attributes' types will be determined at the first time parsed, if conflicts detected shortly
-/// after, an error msg will be emitted
+/// Date times are only detected in RFC 3339 form, other forms are declared with `Attr::DateTime` in
+/// `Ctx`, along with the parse format of the column, see `Ctx::set_datetime_format`
+///
+/// Empty cells of a nullable column are `Null`, see `Ctx::set_nullable`
+///
+/// Strings of a column declared with `Attr::Category` are interned by `Ctx` into small ids, so
+/// equal strings are equal ids sharing one string, see `Ctx::category_name`
+///
+/// Attributes' types will be determined at the first time parsed, values which conflict with them
+/// fail `Record::new` with a `ParseError`, or are all reported by `Ctx::check_consistency`
 ///
 /// It is small enough to be copyable
-#[derive(PartialEq, PartialOrd, Debug)]
+///
+/// Attributes are totally ordered, so they can be sorted, hashed and used as map keys. Floats are
+/// compared with `f32::total_cmp`, that is `-0.0 < 0.0` and NaNs are the smallest or largest
+/// values depending on their sign. Attributes of different variants are ordered by variant, in
+/// declaration order regardless of their values: `Int < Int64 < Float < Decimal < Bool < Str <
+/// Category < DateTime < Null`, e.g. `Int(5) < Float(1.0)` and any `Str` is greater than any
+/// number. Categories are ordered by id, that is the order their strings are first parsed in
+#[derive(Clone, Debug)]
 pub enum Attr {
     Int(i32),
+    Int64(i64),
     Float(f32),
+    /// Exact fixed-point number, like money, it's never detected and has to be declared in `Ctx`.
+    /// Decimals equal by value, so `1.0` and `1.00` are in the same group
+    #[cfg(feature = "decimal")]
+    Decimal(Decimal),
     Bool(bool),
     Str(String),
+    /// Id of an interned string and the string itself, it's never detected and has to be
+    /// declared in `Ctx`, like `Attr::Category(0, "".into())`. Categories equal and hash by id,
+    /// they're displayed as their strings
+    Category(u32, Arc<str>),
+    DateTime(NaiveDateTime),
+    Null,
 }
 
 impl Attr {
-    fn new(ctx: &Ctx, header: &str, raw_attr: &str) -> Self {
-        match ctx.attr_type.get(header).expect(&format!(
-            "Error: header `{}' is not found in context info",
-            header
-        )) {
-            Attr::Int(_) => Attr::Int(raw_attr.parse::<i32>().expect(&format!(
-                "Error: expect int when parsing attribute `{}', which value is `{}'",
-                header, raw_attr
-            ))),
-            Attr::Float(_) => Attr::Float(raw_attr.parse::<f32>().expect(&format!(
-                "Error: expect float when parsing attribute `{}', which value is `{}'",
-                header, raw_attr
-            ))),
-            Attr::Bool(_) => Attr::Bool(match raw_attr {
-                "true" | "True" | "TRUE" | "t" | "T" => true,
-                "false" | "False" | "FALSE" | "f" | "F" => false,
-                _ => panic!(
-                    "Error: expect bool when parsing attribute `{}', which value is `{}'",
-                    header, raw_attr
-                ),
-            }),
-            Attr::Str(_) => Attr::Str(raw_attr.into()),
+    fn new(ctx: &Ctx, header: &str, raw_attr: &str) -> Result<Self, Error> {
+        Self::parse(ctx, header, raw_attr, true)
+    }
+
+    /// Strings of categories which aren't interned yet are interned if `intern`, otherwise
+    /// they're given an id which no string is interned as
+    fn parse(ctx: &Ctx, header: &str, raw_attr: &str, intern: bool) -> Result<Self, Error> {
+        let parse_error = |expected| ParseError {
+            header: header.into(),
+            raw_attr: raw_attr.into(),
+            expected,
+        };
+        let name = ctx.canonical(header);
+        let attr_type = ctx
+            .attr_type
+            .get(name)
+            .ok_or_else(|| Error::MissingHeader(header.into()))?;
+        let trim = match attr_type {
+            Attr::Str(_) | Attr::Category(..) => ctx.trim_strings,
+            _ => ctx.trim,
+        };
+        let raw_attr = if trim { raw_attr.trim() } else { raw_attr };
+        if ctx.nullable.contains(name) && ctx.null_literals.iter().any(|x| x == raw_attr) {
+            return Ok(Attr::Null);
         }
+        Ok(match attr_type {
+            Attr::Int(_) => Attr::Int(raw_attr.parse::<i32>().map_err(|_| parse_error("int"))?),
+            Attr::Int64(_) => {
+                Attr::Int64(raw_attr.parse::<i64>().map_err(|_| parse_error("int64"))?)
+            }
+            Attr::Float(_) => {
+                let v = raw_attr.parse::<f32>().map_err(|_| parse_error("float"))?;
+                match ctx.non_finite {
+                    _ if v.is_finite() => Attr::Float(v),
+                    NonFinite::Propagate => Attr::Float(v),
+                    NonFinite::Skip => Attr::Null,
+                    NonFinite::Error => return Err(parse_error("finite float").into()),
+                }
+            }
+            #[cfg(feature = "decimal")]
+            Attr::Decimal(_) => Attr::Decimal(
+                raw_attr
+                    .parse::<Decimal>()
+                    .map_err(|_| parse_error("decimal"))?,
+            ),
+            Attr::Bool(_) => Attr::Bool(
+                parse_bool(raw_attr, ctx.bool_literals.get(name))
+                    .ok_or_else(|| parse_error("bool"))?,
+            ),
+            Attr::Str(_) => Attr::Str(raw_attr.into()),
+            Attr::Category(..) if intern => {
+                let (id, name) = ctx.intern(raw_attr);
+                Attr::Category(id, name)
+            }
+            Attr::Category(..) => match ctx.category_id(raw_attr) {
+                Some(id) => Attr::Category(id, ctx.category_name(id).unwrap()),
+                None => Attr::Category(UNKNOWN_CATEGORY, raw_attr.into()),
+            },
+            Attr::DateTime(_) => Attr::DateTime(
+                parse_datetime(raw_attr, ctx.datetime_format.get(name))
+                    .ok_or_else(|| parse_error("datetime"))?,
+            ),
+            Attr::Null => Attr::Null,
+        })
     }
 }
 
-/// Data record, looks up attribute's value by name
-pub struct Record {
-    attrs: HashMap<String, Attr>,
-    group_id: u64,
+/// Category id of the strings which aren't interned, see `Attr::parse`
+const UNKNOWN_CATEGORY: u32 = u32::MAX;
+
+/// Whether the digits start with a redundant zero, like `007` or `-01`
+fn has_leading_zero(raw_attr: &str) -> bool {
+    let digits = raw_attr
+        .strip_prefix(['-', '+'])
+        .unwrap_or(raw_attr)
+        .as_bytes();
+    matches!(digits, [b'0', digit, ..] if digit.is_ascii_digit())
 }
 
-impl Record {
-    /// raw_record: vector of (header, value)
-    pub fn new(ctx: &Ctx, raw_record: Vec<(&str, &str)>) -> Self {
-        let attrs: HashMap<String, Attr> = raw_record
-            .into_iter()
-            .map(|(header, raw_attr)| (header.into(), Attr::new(ctx, header, raw_attr)))
-            .collect();
+/// Parse with the literals if given, otherwise as one of `true`, `True`, `TRUE`, `t`, `T` and
+/// their false equivalents
+fn parse_bool(raw_attr: &str, literals: Option<&BoolLiterals>) -> Option<bool> {
+    match literals {
+        Some(literals) if literals.truthy.iter().any(|x| x == raw_attr) => Some(true),
+        Some(literals) if literals.falsy.iter().any(|x| x == raw_attr) => Some(false),
+        Some(_) => None,
+        None => match raw_attr {
+            "true" | "True" | "TRUE" | "t" | "T" => Some(true),
+            "false" | "False" | "FALSE" | "f" | "F" => Some(false),
+            _ => None,
+        },
+    }
+}
 
-        // Hash the group id by rule
-        let mut hasher = DefaultHasher::new();
-        ctx.group_by.iter().for_each(|(attr_name, rule)| {
-            match attrs
-                .get(attr_name)
-                .expect("Error: key attribute is not found")
-            {
-                Attr::Int(v) => match rule {
-                    ComponentRule::Unique => v.hash(&mut hasher),
-                    ComponentRule::Interval(interval) => {
-                        ((v - interval.start) / interval.step).hash(&mut hasher)
-                    }
-                },
-                Attr::Float(v) => match rule {
-                    ComponentRule::Unique => (v.trunc() as i32).hash(&mut hasher),
-                    ComponentRule::Interval(interval) => {
-                        (((v.trunc() as i32) - interval.start) / interval.step).hash(&mut hasher)
-                    }
-                },
-                Attr::Bool(v) => v.hash(&mut hasher),
-                Attr::Str(v) => v.hash(&mut hasher),
-            }
-        });
-        let group_id = hasher.finish();
+/// Parse with the format if given, otherwise as RFC 3339 and converted to UTC
+fn parse_datetime(raw_attr: &str, format: Option<&String>) -> Option<NaiveDateTime> {
+    match format {
+        Some(format) => NaiveDateTime::parse_from_str(raw_attr, format).ok(),
+        None => DateTime::parse_from_rfc3339(raw_attr)
+            .ok()
+            .map(|x| x.naive_utc()),
+    }
+}
+
+impl Attr {
+    /// Name of the type in parse errors, like `int`
+    fn type_name(&self) -> &'static str {
+        match self {
+            Attr::Int(_) => "int",
+            Attr::Int64(_) => "int64",
+            Attr::Float(_) => "float",
+            #[cfg(feature = "decimal")]
+            Attr::Decimal(_) => "decimal",
+            Attr::Bool(_) => "bool",
+            Attr::Str(_) => "str",
+            Attr::Category(..) => "category",
+            Attr::DateTime(_) => "datetime",
+            Attr::Null => "null",
+        }
+    }
+
+    fn rank(&self) -> u8 {
+        match self {
+            Attr::Int(_) => 0,
+            Attr::Int64(_) => 1,
+            Attr::Float(_) => 2,
+            #[cfg(feature = "decimal")]
+            Attr::Decimal(_) => 3,
+            Attr::Bool(_) => 4,
+            Attr::Str(_) => 5,
+            Attr::Category(..) => 6,
+            Attr::DateTime(_) => 7,
+            Attr::Null => 8,
+        }
+    }
+
+    /// Value of a numeric attribute, widened to f64
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Attr::Int(v) => Some(v.to_owned() as f64),
+            Attr::Int64(v) => Some(v.to_owned() as f64),
+            Attr::Float(v) => Some(v.to_owned() as f64),
+            #[cfg(feature = "decimal")]
+            Attr::Decimal(v) => v.to_f64(),
+            _ => None,
+        }
+    }
+
+    /// Value of an integer attribute, floats are not converted
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Attr::Int(v) => Some(v.to_owned() as i64),
+            Attr::Int64(v) => Some(v.to_owned()),
+            _ => None,
+        }
+    }
 
-        Record { attrs, group_id }
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Attr::Bool(v) => Some(v.to_owned()),
+            _ => None,
+        }
+    }
+
+    fn is_nan(&self) -> bool {
+        matches!(self, Attr::Float(v) if v.is_nan())
+    }
+
+    /// Compare by value, numeric attributes of different types are compared as numbers, like
+    /// `Int(1) < Float(1.5)`, other attributes like `Ord`. `None` if either is NaN
+    fn cmp_value(&self, other: &Attr) -> Option<Ordering> {
+        if self.is_nan() || other.is_nan() {
+            return None;
+        }
+        if self.rank() == other.rank() {
+            return Some(self.cmp(other));
+        }
+        match (self.as_i64(), other.as_i64(), self.as_f64(), other.as_f64()) {
+            (Some(x), Some(y), ..) => Some(x.cmp(&y)),
+            (.., Some(x), Some(y)) => x.partial_cmp(&y),
+            _ => Some(self.cmp(other)),
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Attr::Str(v) => Some(v.as_str()),
+            _ => None,
+        }
     }
 }
 
-pub struct Interval {
-    start: i32,
-    step: i32,
+impl Ord for Attr {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Attr::Int(x), Attr::Int(y)) => x.cmp(y),
+            (Attr::Int64(x), Attr::Int64(y)) => x.cmp(y),
+            (Attr::Float(x), Attr::Float(y)) => x.total_cmp(y),
+            #[cfg(feature = "decimal")]
+            (Attr::Decimal(x), Attr::Decimal(y)) => x.cmp(y),
+            (Attr::Bool(x), Attr::Bool(y)) => x.cmp(y),
+            (Attr::Str(x), Attr::Str(y)) => x.cmp(y),
+            // Ids are unique among the strings interned, not the unknown ones
+            (Attr::Category(x, x_name), Attr::Category(y, y_name)) => x.cmp(y).then_with(|| {
+                if Arc::ptr_eq(x_name, y_name) {
+                    Ordering::Equal
+                } else {
+                    x_name.cmp(y_name)
+                }
+            }),
+            (Attr::DateTime(x), Attr::DateTime(y)) => x.cmp(y),
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
 }
 
-pub enum ComponentRule {
-    Unique,
-    Interval(Interval),
+impl PartialOrd for Attr {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
 }
 
-/// Set context:
-///   attributes' types
-///   definition of group by
-pub struct Ctx {
-    attr_type: HashMap<String, Attr>,
-    group_by: HashMap<String, ComponentRule>,
+/// Floats are equal if they have the same bit pattern, consistent with `Ord`
+impl PartialEq for Attr {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
 }
 
-impl Ctx {
-    pub fn new() -> Self {
-        Ctx {
-            attr_type: HashMap::new(),
-            group_by: HashMap::new(),
+impl Eq for Attr {}
+
+impl Hash for Attr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.rank().hash(state);
+        match self {
+            Attr::Int(v) => v.hash(state),
+            Attr::Int64(v) => v.hash(state),
+            Attr::Float(v) => v.to_bits().hash(state),
+            #[cfg(feature = "decimal")]
+            Attr::Decimal(v) => v.hash(state),
+            Attr::Bool(v) => v.hash(state),
+            Attr::Str(v) => v.hash(state),
+            Attr::Category(id, _) => id.hash(state),
+            Attr::DateTime(v) => v.hash(state),
+            Attr::Null => (),
         }
     }
+}
 
-    pub fn add_attr_type(
-        &mut self,
-        attr_name: &str,
-        attr_type: Attr,
-        group_by: Option<ComponentRule>,
-    ) {
-        self.attr_type.insert(attr_name.into(), attr_type);
-        if let Some(group_by_component) = group_by {
-            self.group_by.insert(attr_name.into(), group_by_component);
+/// `Null` is displayed as an empty string, so it's written back as an empty cell. Floats are
+/// displayed as short as they round trip, or rounded to the precision of the formatter, like
+/// `{:.2}`
+impl fmt::Display for Attr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Attr::Int(v) => write!(f, "{}", v),
+            Attr::Int64(v) => write!(f, "{}", v),
+            Attr::Float(v) => match f.precision() {
+                Some(precision) => write!(f, "{:.*}", precision, v),
+                None => write!(f, "{}", v),
+            },
+            #[cfg(feature = "decimal")]
+            Attr::Decimal(v) => write!(f, "{}", v),
+            Attr::Bool(v) => write!(f, "{}", v),
+            Attr::Str(v) => write!(f, "{}", v),
+            Attr::Category(_, name) => write!(f, "{}", name),
+            Attr::DateTime(v) => write!(f, "{}", v.format("%Y-%m-%dT%H:%M:%S%.f")),
+            Attr::Null => Ok(()),
         }
     }
 }
 
-/// A group is a set of Record with same `group_id`s. Records are never changed, so here stores
-/// `Record`s references
-#[derive(Clone)]
-pub struct Group<'a> {
-    records: HashSet<ByAddress<&'a Record>>,
-    id: u64,
+impl Attr {
+    /// Display the attribute with floats rounded to `precision` decimals, when it's given
+    pub fn to_string_with_precision(&self, precision: Option<usize>) -> String {
+        match precision {
+            Some(precision) => format!("{:.*}", precision, self),
+            None => self.to_string(),
+        }
+    }
 }
 
-impl<'a> Group<'a> {
-    fn new(id: u64) -> Self {
-        Self {
-            records: HashSet::new(),
-            id,
+#[cfg(feature = "json")]
+impl Attr {
+    /// Json value of the attribute: numbers and bools as they are, floats as displayed, so `0.1`
+    /// stays `0.1`, and NaN or infinity as `null`. Decimals, categories and date times are
+    /// strings
+    pub fn to_json(&self) -> serde_json::Value {
+        use serde_json::Value;
+
+        match self {
+            Attr::Int(v) => Value::from(*v),
+            Attr::Int64(v) => Value::from(*v),
+            Attr::Float(v) => self
+                .to_string()
+                .parse::<f64>()
+                .ok()
+                .filter(|_| v.is_finite())
+                .map_or(Value::Null, Value::from),
+            #[cfg(feature = "decimal")]
+            Attr::Decimal(_) => Value::String(self.to_string()),
+            Attr::Bool(v) => Value::Bool(*v),
+            Attr::Str(v) => Value::String(v.clone()),
+            Attr::Category(_, name) => Value::String(name.to_string()),
+            Attr::DateTime(_) => Value::String(self.to_string()),
+            Attr::Null => Value::Null,
         }
     }
 }
 
-/// A Collection is a set of groups, with full or part of records in them.
-#[derive(Clone)]
-pub struct Collection<'a> {
-    groups: HashMap<u64, Group<'a>>,
+/// Serialized untagged, like `3`, `0.5`, `true` or `"x"`. Date times and decimals are serialized as
+/// strings in the `Display` form, and `Null` as unit
+#[cfg(feature = "serde")]
+impl serde::Serialize for Attr {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Attr::Int(v) => serializer.serialize_i32(*v),
+            Attr::Int64(v) => serializer.serialize_i64(*v),
+            Attr::Float(v) => serializer.serialize_f32(*v),
+            #[cfg(feature = "decimal")]
+            Attr::Decimal(_) => serializer.serialize_str(&self.to_string()),
+            Attr::Bool(v) => serializer.serialize_bool(*v),
+            Attr::Str(v) => serializer.serialize_str(v),
+            Attr::Category(_, name) => serializer.serialize_str(name),
+            Attr::DateTime(_) => serializer.serialize_str(&self.to_string()),
+            Attr::Null => serializer.serialize_unit(),
+        }
+    }
 }
 
-// TODO: This implementation is temporary
-pub struct FilterCond {
-    attr_name: String,
-    val: Attr,
-    ord: Ordering,
-}
+/// Deserialized as the most specific variant: integers are `Int` if they fit in i32 and `Int64`
+/// otherwise. Strings are always `Str`, since date times can't be told apart without `Ctx`
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Attr {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct AttrVisitor;
 
-impl<'a> Collection<'a> {
-    pub fn new(records: Vec<&'a Record>) -> Self {
-        let mut groups: HashMap<u64, Group> = HashMap::new();
-        records.into_iter().for_each(|record| {
-            if !groups.contains_key(&record.group_id) {
-                groups.insert(record.group_id, Group::new(record.group_id));
+        impl<'de> serde::de::Visitor<'de> for AttrVisitor {
+            type Value = Attr;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "an int, float, bool, string or null")
             }
-            groups
-                .get_mut(&record.group_id)
-                .expect("Impossible: key is not found when creating new collection")
-                .records
-                .insert(ByAddress(&record));
-        });
-        Self { groups }
+
+            fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<Attr, E> {
+                Ok(i32::try_from(v).map_or(Attr::Int64(v), Attr::Int))
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Attr, E> {
+                i64::try_from(v)
+                    .map(|v| self.visit_i64(v))
+                    .unwrap_or_else(|_| Err(E::custom(format!("int `{}' is too large", v))))
+            }
+
+            fn visit_f64<E: serde::de::Error>(self, v: f64) -> Result<Attr, E> {
+                Ok(Attr::Float(v as f32))
+            }
+
+            fn visit_bool<E: serde::de::Error>(self, v: bool) -> Result<Attr, E> {
+                Ok(Attr::Bool(v))
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Attr, E> {
+                Ok(Attr::Str(v.into()))
+            }
+
+            fn visit_unit<E: serde::de::Error>(self) -> Result<Attr, E> {
+                Ok(Attr::Null)
+            }
+
+            fn visit_none<E: serde::de::Error>(self) -> Result<Attr, E> {
+                Ok(Attr::Null)
+            }
+        }
+
+        deserializer.deserialize_any(AttrVisitor)
     }
+}
 
-    /// Filter the collection with predicate, generate new collection
-    // TODO: Filter cond need reimplementation
-    pub fn filter_records(mut self, filter_cond: FilterCond) -> Self {
-        self.groups = self
-            .groups
-            .into_iter()
-            .filter_map(|(id, group)| {
-                let records: HashSet<ByAddress<&Record>> = group
-                    .records
-                    .into_iter()
-                    .filter(|record| {
-                        record
-                            .attrs
-                            .get(&filter_cond.attr_name)
-                            .partial_cmp(&Some(&filter_cond.val))
-                            == Some(filter_cond.ord)
-                    })
-                    .collect();
-                if records.is_empty() {
-                    None
-                } else {
-                    Some((
-                        id.to_owned(),
-                        Group {
-                            records,
-                            id: id.to_owned(),
-                        },
-                    ))
-                }
-            })
-            .collect();
-        self
+/// Error emitted when a raw attribute can't be parsed as the type declared in `Ctx`
+#[derive(PartialEq, Debug)]
+pub struct ParseError {
+    pub header: String,
+    pub raw_attr: String,
+    /// Name of the declared type, like `int` or `float`
+    pub expected: &'static str,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Error: expect {} when parsing attribute `{}', which value is `{}'",
+            self.expected, self.header, self.raw_attr
+        )
     }
+}
 
-    pub fn intersection(mut self, other: &Self) -> Self {
-        self.groups = self
-            .groups
+impl error::Error for ParseError {}
+
+/// A value which conflicts with its declared type, found by `Ctx::check_consistency`
+#[derive(PartialEq, Debug)]
+pub struct TypeConflict {
+    pub row: usize,
+    pub error: ParseError,
+}
+
+impl fmt::Display for TypeConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "row {}: {}", self.row, self.error)
+    }
+}
+
+/// A row which can't be built into a record, collected by `Record::parse_batch`
+#[derive(Debug)]
+pub struct RowError {
+    pub row: usize,
+    pub error: Error,
+}
+
+impl fmt::Display for RowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "row {}: {}", self.row, self.error)
+    }
+}
+
+impl error::Error for RowError {}
+
+/// Data record, looks up attribute's value by name, attributes are kept in the order of headers
+#[derive(Clone)]
+pub struct Record {
+    attrs: IndexMap<String, Attr>,
+    group_id: u64,
+    /// Group-by attributes and their representative values, `group_id` is hashed from it
+    group_key: Vec<(String, Attr)>,
+}
+
+impl Record {
+    /// raw_record: vector of (header, value)
+    ///
+    /// Fails with the first attribute which can't be parsed as its declared type, or isn't declared
+    /// at all unless `Ctx::set_unknown_column_policy` allows it, and when a group-by attribute is
+    /// absent
+    pub fn new(ctx: &Ctx, raw_record: Vec<(&str, &str)>) -> Result<Self, Error> {
+        let attrs: IndexMap<String, Attr> = raw_record
             .into_iter()
-            .filter_map(|(id, group)| {
-                if let Some(other_group) = other.groups.get(&id) {
-                    let records: HashSet<_> = group
-                        .records
-                        .intersection(&other_group.records)
-                        .map(|x| x.to_owned())
-                        .collect();
-                    if records.is_empty() {
-                        None
-                    } else {
-                        Some((
-                            id.to_owned(),
-                            Group {
-                                records,
-                                id: id.to_owned(),
-                            },
-                        ))
+            .filter_map(|(header, raw_attr)| {
+                let name = ctx.canonical(header);
+                match ctx.unknown_column {
+                    _ if ctx.attr_type.contains_key(name) => {
+                        Some(Attr::new(ctx, header, raw_attr).map(|attr| (name.into(), attr)))
                     }
-                } else {
-                    None
+                    UnknownColumn::Error => Some(Err(Error::MissingHeader(header.into()))),
+                    UnknownColumn::AsString => {
+                        let raw_attr = if ctx.trim_strings {
+                            raw_attr.trim()
+                        } else {
+                            raw_attr
+                        };
+                        Some(Ok((name.into(), Attr::Str(raw_attr.into()))))
+                    }
+                    UnknownColumn::Ignore => None,
                 }
             })
-            .collect();
-        self
+            .collect::<Result<_, Error>>()?;
+        Self::from_attrs(ctx, attrs)
     }
 
-    pub fn union(mut self, other: &Self) -> Self {
-        other.groups.iter().for_each(|(id, other_group)| {
-            if let Some(mut group) = self.groups.get_mut(id) {
-                group.records = group
-                    .records
-                    .union(&other_group.records)
-                    .map(|x| x.to_owned())
-                    .collect();
-            } else {
-                self.groups.insert(id.to_owned(), other_group.to_owned());
+    /// Build a record of every row, each in the order of `headers`, keeping the good ones and
+    /// the failures of the bad ones instead of stopping at the first failure. Rows are numbered
+    /// from 0, both records and errors are in the order of rows
+    pub fn parse_batch(
+        ctx: &Ctx,
+        headers: &[&str],
+        rows: &[Vec<&str>],
+    ) -> (Vec<Record>, Vec<RowError>) {
+        let mut records = Vec::new();
+        let mut errors = Vec::new();
+        for (row, raw_record) in rows.iter().enumerate() {
+            match Self::new(
+                ctx,
+                zip(headers.iter().copied(), raw_record.iter().copied()).collect(),
+            ) {
+                Ok(record) => records.push(record),
+                Err(error) => errors.push(RowError { row, error }),
             }
-        });
-        self
+        }
+        (records, errors)
     }
 
-    pub fn difference(mut self, other: &Self) -> Self {
-        self.groups = self
-            .groups
+    /// Build a record from attributes which are already typed, skipping the parsing. Each
+    /// attribute should be of its declared type, or `Null` if it's nullable, values are taken as
+    /// they are. Computed attributes and the group id are evaluated like `new`
+    pub fn from_typed(ctx: &Ctx, attrs: Vec<(&str, Attr)>) -> Result<Self, Error> {
+        let attrs: IndexMap<String, Attr> = attrs
             .into_iter()
-            .filter_map(|(id, group)| {
-                if let Some(other_group) = other.groups.get(&id) {
-                    let records: HashSet<_> = group
-                        .records
-                        .difference(&other_group.records)
-                        .map(|x| x.to_owned())
-                        .collect();
-                    if records.is_empty() {
-                        None
-                    } else {
-                        Some((
-                            id.to_owned(),
-                            Group {
-                                records,
-                                id: id.to_owned(),
-                            },
-                        ))
-                    }
-                } else {
-                    None
+            .map(|(header, attr)| {
+                let name = ctx.canonical(header);
+                let attr_type = ctx
+                    .attr_type
+                    .get(name)
+                    .ok_or_else(|| Error::MissingHeader(header.into()))?;
+                let matched = match attr {
+                    Attr::Null => ctx.nullable.contains(name),
+                    _ => attr.rank() == attr_type.rank(),
+                };
+                if !matched {
+                    return Err(Error::Parse(ParseError {
+                        header: header.into(),
+                        raw_attr: attr.to_string(),
+                        expected: attr_type.type_name(),
+                    }));
                 }
+                Ok((name.into(), attr))
             })
-            .collect();
-        self
+            .collect::<Result<_, Error>>()?;
+        Self::from_attrs(ctx, attrs)
     }
 
-    // Handle fold operation
-    pub fn fold(&self, op: FoldOperation) -> FoldResult {
-        match op {
-            FoldOperation::AVG(attr_name) => self.avg(&attr_name),
-            FoldOperation::SUM(attr_name) => self.sum(&attr_name),
-            FoldOperation::COUNT => self.count(),
-        }
+    /// Evaluate computed attributes and the group id of the parsed attributes
+    fn from_attrs(ctx: &Ctx, mut attrs: IndexMap<String, Attr>) -> Result<Self, Error> {
+        ctx.computed.iter().for_each(|(attr_name, compute)| {
+            let attr = compute(&attrs);
+            attrs.insert(attr_name.to_owned(), attr);
+        });
+
+        let (group_id, group_key) = Self::group_of(ctx, &attrs)?;
+        Ok(Record {
+            attrs,
+            group_id,
+            group_key,
+        })
     }
 
-    fn avg(&self, attr_name: &str) -> FoldResult {
-        let result: HashMap<ByAddress<&Group>, Attr> = self
-            .groups
+    /// Hash the group id by the rules of `ctx`, along with the group key it's hashed from
+    fn group_of(
+        ctx: &Ctx,
+        attrs: &IndexMap<String, Attr>,
+    ) -> Result<(u64, Vec<(String, Attr)>), Error> {
+        let group_key: Vec<(String, Attr)> = ctx
+            .group_by
             .iter()
-            .map(|(_, group)| {
-                let (sum, count) = group.records.iter().fold((0f32, 0i32), |(sum, count), x| {
-                    (
-                        sum + match x.attrs.get(attr_name).unwrap_or(&Attr::Float(0f32)) {
-                            Attr::Int(v) => v.to_owned() as f32,
-                            Attr::Float(v) => v.to_owned(),
-                            _ => panic!("AVG operation should be performed on int or float"),
-                        },
-                        count + 1,
-                    )
-                });
-                (ByAddress(group), Attr::Float(sum / (count as f32)))
+            .map(|(attr_name, rule)| {
+                let attr = attrs
+                    .get(attr_name)
+                    .ok_or_else(|| Error::MissingGroupKey(attr_name.to_owned()))?;
+                Ok((attr_name.to_owned(), rule.key_of(attr)))
             })
-            .collect();
-        FoldResult {
-            collection: ByAddress(self),
-            fold_func: FoldOperation::AVG(attr_name.into()),
-            result,
-        }
+            .collect::<Result<_, Error>>()?;
+        let mut hasher = DefaultHasher::new();
+        group_key.iter().for_each(|(_, key)| key.hash(&mut hasher));
+        Ok((hasher.finish(), group_key))
     }
+}
 
-    fn sum(&self, attr_name: &str) -> FoldResult {
-        let result: HashMap<ByAddress<&Group>, Attr> = self
-            .groups
-            .iter()
-            .map(|(_, group)| {
-                let sum = group.records.iter().fold(0f32, |acc, x| {
-                    acc + match x.attrs.get(attr_name).unwrap_or(&Attr::Float(0f32)) {
-                        Attr::Int(v) => v.to_owned() as f32,
-                        Attr::Float(v) => v.to_owned(),
-                        _ => panic!("AVG operation should be performed on int or float"),
-                    }
-                });
-                (ByAddress(group), Attr::Float(sum))
-            })
-            .collect();
-        FoldResult {
-            collection: ByAddress(self),
-            fold_func: FoldOperation::SUM(attr_name.into()),
-            result,
-        }
+impl Record {
+    /// Value of the attribute named `attr_name`, `None` if the record doesn't have it
+    ///
+    /// ```
+    /// use generic_table_processor::data_represent::{Attr, ComponentRule, Ctx, Record};
+    ///
+    /// let mut ctx = Ctx::new();
+    /// ctx.add_attr_type("userid", Attr::Int(0), Some(ComponentRule::Unique));
+    /// let record = Record::new(&ctx, vec![("userid", "7")]).unwrap();
+    /// assert_eq!(record.get("userid"), Some(&Attr::Int(7)));
+    /// assert_eq!(record.get("time"), None);
+    /// ```
+    pub fn get(&self, attr_name: &str) -> Option<&Attr> {
+        self.attrs.get(attr_name)
     }
 
-    fn count(&self) -> FoldResult {
-        let result: HashMap<ByAddress<&Group>, Attr> = self
-            .groups
+    /// Id of the group the record belongs to, hashed from its group-by attributes
+    pub fn group_id(&self) -> u64 {
+        self.group_id
+    }
+
+    /// Attribute names and values in the order of headers, computed attributes last
+    pub fn attrs(&self) -> impl Iterator<Item = (&str, &Attr)> {
+        self.attrs.iter().map(|(name, attr)| (name.as_str(), attr))
+    }
+
+    /// The attributes named by `names` in their order, absent ones are skipped
+    pub fn project(&self, names: &[&str]) -> IndexMap<String, &Attr> {
+        names
             .iter()
-            .map(|(_, group)| {
-                (
-                    ByAddress(group),
-                    Attr::Int(group.records.iter().count() as i32),
-                )
-            })
-            .collect();
-        FoldResult {
-            collection: ByAddress(self),
-            fold_func: FoldOperation::COUNT,
-            result,
+            .filter_map(|&name| self.attrs.get(name).map(|attr| (name.to_owned(), attr)))
+            .collect()
+    }
+
+    fn cmp_by(&self, other: &Self, attr_name: &str, ascending: bool) -> Ordering {
+        fn value<'r>(record: &'r Record, attr_name: &str) -> Option<&'r Attr> {
+            record
+                .attrs
+                .get(attr_name)
+                .filter(|&attr| *attr != Attr::Null)
+        }
+        match (value(self, attr_name), value(other, attr_name)) {
+            (Some(x), Some(y)) if ascending => x.cmp(y),
+            (Some(x), Some(y)) => y.cmp(x),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
         }
+        .then_with(|| self.attrs.values().cmp(other.attrs.values()))
     }
 }
 
-pub enum FoldOperation {
-    AVG(String), // AVG of attr
-    SUM(String), // SUM of attr
-    COUNT,       // items count
+/// Records equal by value when they have the same attributes in the same order, while groups
+/// tell records apart by address, see `Collection::dedup_by_value`
+impl PartialEq for Record {
+    fn eq(&self, other: &Self) -> bool {
+        self.attrs.iter().eq(other.attrs.iter())
+    }
 }
 
-/// FoldResult is binding to collection and fold_func, and mapping each group to a scalar result
-pub struct FoldResult<'a> {
-    collection: ByAddress<&'a Collection<'a>>,
-    fold_func: FoldOperation,
-    result: HashMap<ByAddress<&'a Group<'a>>, Attr>,
+impl Eq for Record {}
+
+impl Hash for Record {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.attrs.len().hash(state);
+        self.attrs.iter().for_each(|attr| attr.hash(state));
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::iter::zip;
+/// Owned record produced by `Collection::inner_join`, attributes are kept in join order
+#[derive(Clone, PartialEq, Debug)]
+pub struct JoinedRecord {
+    attrs: IndexMap<String, Attr>,
+}
+
+impl JoinedRecord {
+    pub fn get(&self, attr_name: &str) -> Option<&Attr> {
+        self.attrs.get(attr_name)
+    }
+
+    /// Attribute names and values, left record's first
+    pub fn attrs(&self) -> impl Iterator<Item = (&str, &Attr)> {
+        self.attrs.iter().map(|(name, attr)| (name.as_str(), attr))
+    }
+
+    /// Write joined records as csv, headers are the attribute names of the first record. No
+    /// headers are written without records
+    pub fn write_csv<W: io::Write>(records: &[JoinedRecord], wtr: W) -> Result<(), csv::Error> {
+        Self::write_csv_with_precision(records, wtr, None)
+    }
+
+    /// `write_csv` with floats rounded to `precision` decimals, when it's given
+    pub fn write_csv_with_precision<W: io::Write>(
+        records: &[JoinedRecord],
+        wtr: W,
+        precision: Option<usize>,
+    ) -> Result<(), csv::Error> {
+        let mut wtr = csv::Writer::from_writer(wtr);
+        if let Some(first) = records.first() {
+            wtr.write_record(first.attrs.keys())?;
+        }
+        for record in records {
+            wtr.write_record(
+                record
+                    .attrs
+                    .values()
+                    .map(|attr| attr.to_string_with_precision(precision)),
+            )?;
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+}
+
+/// Buckets a numeric attribute into `[start + k * step, start + (k + 1) * step)`, works for both
+/// `Int` and `Int64` attributes. For `DateTime` attributes, `start` and `step` are seconds since
+/// Unix epoch
+pub struct Interval {
+    start: i32,
+    step: i32,
+}
+
+impl Interval {
+    /// Panics if `step` isn't positive, see `try_new`
+    pub fn new(start: i32, step: i32) -> Self {
+        Self::try_new(start, step)
+            .unwrap_or_else(|| panic!("Error: step of interval should be positive, got {}", step))
+    }
+
+    /// `None` if `step` isn't positive
+    pub fn try_new(start: i32, step: i32) -> Option<Self> {
+        (step > 0).then_some(Interval { start, step })
+    }
+}
+
+/// Buckets a `DateTime` attribute into `[origin + k * size, origin + (k + 1) * size)`, with
+/// millisecond precision. Times before `origin` are floored into negative buckets
+pub struct TimeBucket {
+    size: Duration,
+    origin: NaiveDateTime,
+}
+
+impl TimeBucket {
+    /// Panics if `size` is less than 1ms, see `try_new`
+    pub fn new(size: Duration, origin: NaiveDateTime) -> Self {
+        Self::try_new(size, origin).unwrap_or_else(|| {
+            panic!(
+                "Error: size of time bucket should be at least 1ms, got {}",
+                size
+            )
+        })
+    }
+
+    /// `None` if `size` is less than 1ms
+    pub fn try_new(size: Duration, origin: NaiveDateTime) -> Option<Self> {
+        (size.num_milliseconds() > 0).then_some(TimeBucket { size, origin })
+    }
+}
+
+pub enum ComponentRule {
+    Unique,
+    Interval(Interval),
+    TimeBucket(TimeBucket),
+    /// Groups a `Str` attribute by its first `len` chars
+    Prefix {
+        len: usize,
+    },
+    /// Groups a `Str` attribute by its normalized form, lowercased and/or trimmed, so `Hello` and
+    /// ` hello` can be in the same group
+    StrNormalized {
+        lowercase: bool,
+        trim: bool,
+    },
+    /// Buckets a numeric attribute into non-uniform bins, ascending boundaries `b` make the bins
+    /// `[b[k], b[k + 1])`, keyed by `Attr::Int(k)`. Values outside all bins are in an overflow
+    /// bin keyed by `Attr::Int(-1)`. Floats are truncated first
+    Bins(Vec<i32>),
+}
+
+impl ComponentRule {
+    /// Representative value of the attribute's group component, like the start of its interval.
+    /// Rules which don't apply to the attribute's type behave like `Unique`. Floats are floored
+    /// into intervals and truncated otherwise
+    fn key_of(&self, attr: &Attr) -> Attr {
+        match (attr, self) {
+            // The interval holding `i32::MIN` may start below it, so its key is clamped
+            (Attr::Int(v), ComponentRule::Interval(interval)) => {
+                let (start, step) = (interval.start as i64, interval.step as i64);
+                let key = start + (*v as i64 - start).div_euclid(step) * step;
+                Attr::Int(key.max(i32::MIN as i64) as i32)
+            }
+            (Attr::Int64(v), ComponentRule::Interval(interval)) => {
+                let (start, step) = (interval.start as i128, interval.step as i128);
+                let key = start + (*v as i128 - start).div_euclid(step) * step;
+                Attr::Int64(key.max(i64::MIN as i128) as i64)
+            }
+            (Attr::Float(v), ComponentRule::Interval(interval)) if !v.is_nan() => {
+                let (start, step) = (interval.start as f64, interval.step as f64);
+                Attr::Float((start + (*v as f64 - start).div_euclid(step) * step) as f32)
+            }
+            (Attr::Int(v), ComponentRule::Bins(boundaries)) => bin_of(boundaries, *v as i64),
+            (Attr::Int64(v), ComponentRule::Bins(boundaries)) => bin_of(boundaries, *v),
+            (Attr::Float(v), ComponentRule::Bins(boundaries)) if !v.is_nan() => {
+                bin_of(boundaries, v.trunc() as i64)
+            }
+            // Adding zero turns `-0.0` into `0.0`
+            (Attr::Float(v), _) => Attr::Float(v.trunc() + 0f32),
+            (Attr::DateTime(v), ComponentRule::Interval(interval)) => {
+                let (start, step) = (interval.start as i64, interval.step as i64);
+                let seconds = start + (v.and_utc().timestamp() - start).div_euclid(step) * step;
+                Attr::DateTime(
+                    DateTime::from_timestamp(seconds, 0)
+                        .expect("Error: interval of date time is out of range")
+                        .naive_utc(),
+                )
+            }
+            (Attr::DateTime(v), ComponentRule::TimeBucket(TimeBucket { size, origin })) => {
+                let size = size.num_milliseconds();
+                let bucket = (*v - *origin).num_milliseconds().div_euclid(size);
+                Attr::DateTime(*origin + Duration::milliseconds(bucket * size))
+            }
+            (Attr::Str(v), ComponentRule::Prefix { len }) => {
+                Attr::Str(v.chars().take(*len).collect())
+            }
+            (Attr::Str(v), ComponentRule::StrNormalized { lowercase, trim }) => {
+                let v = if *trim { v.trim() } else { v };
+                Attr::Str(if *lowercase {
+                    v.to_lowercase()
+                } else {
+                    v.into()
+                })
+            }
+            _ => attr.clone(),
+        }
+    }
+}
+
+/// Index of the bin holding `v`, see `ComponentRule::Bins`
+fn bin_of(boundaries: &[i32], v: i64) -> Attr {
+    let k = boundaries.partition_point(|&boundary| boundary as i64 <= v);
+    if k == 0 || k == boundaries.len() {
+        Attr::Int(-1)
+    } else {
+        Attr::Int(k as i32 - 1)
+    }
+}
+
+/// Set context:
+///   attributes' types, in the order they are added
+///   definition of group by
+///   nullable attributes, and the literals which are parsed as `Attr::Null` for them
+///   parse formats of date time attributes
+///   literals of bool attributes
+///   computed attributes, in the order they are added
+///   whether surrounding whitespace is trimmed before parsing
+///   how non-finite floats are parsed
+///   strings interned as category ids
+pub struct Ctx {
+    attr_type: IndexMap<String, Attr>,
+    /// Rules are kept sorted by attribute name, so contexts declaring the same rules in any order
+    /// hash the same group ids
+    group_by: BTreeMap<String, ComponentRule>,
+    nullable: HashSet<String>,
+    null_literals: Vec<String>,
+    datetime_format: HashMap<String, String>,
+    bool_literals: HashMap<String, BoolLiterals>,
+    computed: Vec<(String, Computation)>,
+    unknown_column: UnknownColumn,
+    /// Canonical attribute name of each alias
+    aliases: HashMap<String, String>,
+    /// Interned strings of category attributes, a category id is the index of its string
+    categories: RefCell<IndexSet<Arc<str>>>,
+    trim: bool,
+    trim_strings: bool,
+    non_finite: NonFinite,
+}
+
+/// Literals which are parsed as `true` and `false`
+struct BoolLiterals {
+    truthy: Vec<String>,
+    falsy: Vec<String>,
+}
+
+/// How float attributes which are NaN or infinite are parsed, they are kept by default
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum NonFinite {
+    /// Keep them, NaN then propagates through numeric folds
+    Propagate,
+    /// Parse them as `Attr::Null` even if the attribute isn't nullable, so folds skip them
+    Skip,
+    /// Fail with a `ParseError` expecting `finite float`
+    Error,
+}
+
+/// How `Record::new` treats a column which has no declared type, it fails by default
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum UnknownColumn {
+    /// Fail with `Error::MissingHeader`
+    Error,
+    /// Keep it as an `Attr::Str`, trimmed if strings are
+    AsString,
+    /// Leave it out of the record
+    Ignore,
+}
+
+/// Derives an attribute from the parsed ones
+type Computation = Box<dyn Fn(&IndexMap<String, Attr>) -> Attr>;
+
+/// Max number of values per column used by `Ctx::infer`
+pub const INFER_SAMPLE_SIZE: usize = 100;
+
+impl Default for Ctx {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ctx {
+    pub fn new() -> Self {
+        Ctx {
+            attr_type: IndexMap::new(),
+            group_by: BTreeMap::new(),
+            nullable: HashSet::new(),
+            null_literals: vec!["".into(), "NA".into(), "NULL".into()],
+            datetime_format: HashMap::new(),
+            bool_literals: HashMap::new(),
+            computed: Vec::new(),
+            unknown_column: UnknownColumn::Error,
+            aliases: HashMap::new(),
+            categories: RefCell::new(IndexSet::new()),
+            trim: true,
+            trim_strings: false,
+            non_finite: NonFinite::Propagate,
+        }
+    }
+
+    pub fn add_attr_type(
+        &mut self,
+        attr_name: &str,
+        attr_type: Attr,
+        group_by: Option<ComponentRule>,
+    ) {
+        self.attr_type.insert(attr_name.into(), attr_type);
+        if let Some(group_by_component) = group_by {
+            self.group_by.insert(attr_name.into(), group_by_component);
+        }
+    }
+
+    /// Parse headers spelled `alias`, like `user_id` or `userId`, as the attribute `canonical`.
+    /// Records keep it by the canonical name, so it's looked up, grouped and folded by that name
+    /// whichever spelling a table uses. A record having both spellings keeps the latter value
+    pub fn add_alias(&mut self, canonical: &str, alias: &str) {
+        self.aliases.insert(alias.into(), canonical.into());
+    }
+
+    /// Canonical name of a header, which is itself unless it's an alias
+    fn canonical<'b>(&'b self, header: &'b str) -> &'b str {
+        self.aliases
+            .get(header)
+            .map_or(header, |canonical| canonical)
+    }
+
+    /// Category id and shared string of the string, a new id if it's first parsed. Strings of
+    /// all category attributes share the ids
+    fn intern(&self, raw_attr: &str) -> (u32, Arc<str>) {
+        if let Some(id) = self.category_id(raw_attr) {
+            return (id, self.category_name(id).unwrap());
+        }
+        let mut categories = self.categories.borrow_mut();
+        let id = categories.len() as u32;
+        assert!(id < UNKNOWN_CATEGORY, "Error: too many categories");
+        let name: Arc<str> = raw_attr.into();
+        categories.insert(name.clone());
+        (id, name)
+    }
+
+    fn category_id(&self, raw_attr: &str) -> Option<u32> {
+        let categories = self.categories.borrow();
+        categories.get_index_of(raw_attr).map(|id| id as u32)
+    }
+
+    /// String of a category id parsed by the context
+    pub fn category_name(&self, id: u32) -> Option<Arc<str>> {
+        let categories = self.categories.borrow();
+        categories.get_index(id as usize).cloned()
+    }
+
+    /// Group by the attribute with the rule, replacing its rule if it has one, its type is kept
+    pub fn set_group_by(&mut self, attr_name: &str, group_by: ComponentRule) {
+        self.group_by.insert(attr_name.into(), group_by);
+    }
+
+    /// Parse a raw value as the declared type of the attribute, like a filter value given as text,
+    /// category strings aren't interned by it, so one which no record has equals no category
+    pub fn parse_attr(&self, attr_name: &str, raw_attr: &str) -> Result<Attr, Error> {
+        Attr::parse(self, attr_name, raw_attr, false)
+    }
+
+    /// Infer attributes' types from a sample of raw records, each row is in the order of `headers`.
+    /// Up to `INFER_SAMPLE_SIZE` non-null values of every column are tried, and the first type
+    /// of int, int64, float, bool and RFC 3339 date time which all of them can be parsed as is
+    /// chosen, otherwise the attribute is a string. Non-string attributes are nullable if a null
+    /// literal is sampled, values are trimmed as `Ctx::set_trim` does
+    ///
+    /// Numbers with leading zeros, like `007`, are identifiers or codes rather than numbers, so
+    /// a column having any of them is never numeric. `0` and `0.5` are numbers still
+    pub fn infer(headers: &[&str], sample_rows: &[Vec<&str>]) -> Self {
+        Self::infer_with_overrides(headers, sample_rows, &HashMap::new())
+    }
+
+    /// Same as `infer`, but the columns in `overrides` get their types from it whatever the
+    /// sample suggests, like `Attr::Str` for zip codes with leading zeros
+    pub fn infer_with_overrides(
+        headers: &[&str],
+        sample_rows: &[Vec<&str>],
+        overrides: &HashMap<&str, Attr>,
+    ) -> Self {
+        let mut ctx = Ctx::new();
+        headers.iter().enumerate().for_each(|(i, &header)| {
+            let raw_attrs = sample_rows
+                .iter()
+                .filter_map(|row| row.get(i).map(|x| x.trim()));
+            let (nulls, values): (Vec<&str>, Vec<&str>) =
+                raw_attrs.partition(|x| ctx.null_literals.iter().any(|literal| literal == x));
+            let values = &values[..values.len().min(INFER_SAMPLE_SIZE)];
+            let all = |f: fn(&str) -> bool| !values.is_empty() && values.iter().all(|x| f(x));
+            let numeric = !values.iter().any(|x| has_leading_zero(x));
+            let attr_type = if let Some(attr_type) = overrides.get(header) {
+                attr_type.clone()
+            } else if numeric && all(|x| x.parse::<i32>().is_ok()) {
+                Attr::Int(0)
+            } else if numeric && all(|x| x.parse::<i64>().is_ok()) {
+                Attr::Int64(0)
+            } else if numeric && all(|x| x.parse::<f32>().is_ok()) {
+                Attr::Float(0f32)
+            } else if all(|x| parse_bool(x, None).is_some()) {
+                Attr::Bool(false)
+            } else if all(|x| parse_datetime(x, None).is_some()) {
+                Attr::DateTime(NaiveDateTime::default())
+            } else {
+                Attr::Str(String::new())
+            };
+            if !nulls.is_empty() && !matches!(attr_type, Attr::Str(_)) {
+                ctx.set_nullable(header);
+            }
+            ctx.add_attr_type(header, attr_type, None);
+        });
+        ctx
+    }
+
+    /// Parse the date time attribute with a `chrono` format string like `%Y-%m-%d %H:%M:%S`,
+    /// instead of RFC 3339
+    pub fn set_datetime_format(&mut self, attr_name: &str, format: &str) {
+        self.datetime_format.insert(attr_name.into(), format.into());
+    }
+
+    /// Parse the bool attribute with these literals only, like `yes` and `no`, instead of `true`,
+    /// `t`, `false`, `f` and their capitalized forms
+    pub fn set_bool_literals(&mut self, attr_name: &str, truthy: &[&str], falsy: &[&str]) {
+        let literals = BoolLiterals {
+            truthy: truthy.iter().map(|&x| x.into()).collect(),
+            falsy: falsy.iter().map(|&x| x.into()).collect(),
+        };
+        self.bool_literals.insert(attr_name.into(), literals);
+    }
+
+    /// Allow the attribute to be `Attr::Null`, which is parsed from any of the null literals
+    pub fn set_nullable(&mut self, attr_name: &str) {
+        self.nullable.insert(attr_name.into());
+    }
+
+    /// Replace the null literals, default ones are `""`, `NA` and `NULL`
+    pub fn set_null_literals(&mut self, literals: &[&str]) {
+        self.null_literals = literals.iter().map(|&x| x.into()).collect();
+    }
+
+    /// Whether surrounding whitespace of non-string attributes is trimmed before parsing, so
+    /// ` 23 ` is parsed as `23`, it is by default
+    pub fn set_trim(&mut self, trim: bool) {
+        self.trim = trim;
+    }
+
+    /// Whether string attributes are trimmed too, they are kept as is by default
+    pub fn set_trim_strings(&mut self, trim_strings: bool) {
+        self.trim_strings = trim_strings;
+    }
+
+    /// Find every value which can't be parsed as its declared type, instead of only the first one
+    /// like `Record::new`. Each row is in the order of `headers`, rows are numbered from 0,
+    /// conflicts are in the order of rows then columns. Headers absent from the context are not
+    /// checked
+    pub fn check_consistency(&self, headers: &[&str], rows: &[Vec<&str>]) -> Vec<TypeConflict> {
+        rows.iter()
+            .enumerate()
+            .flat_map(|(row, raw_record)| {
+                zip(headers, raw_record)
+                    .filter(|(header, _)| self.attr_type.contains_key(self.canonical(header)))
+                    .filter_map(
+                        move |(header, raw_attr)| match Attr::new(self, header, raw_attr) {
+                            Err(Error::Parse(error)) => Some(TypeConflict { row, error }),
+                            _ => None,
+                        },
+                    )
+            })
+            .collect()
+    }
+
+    pub fn set_non_finite(&mut self, non_finite: NonFinite) {
+        self.non_finite = non_finite;
+    }
+
+    /// How columns without a declared type, nor an alias of one, are parsed by `Record::new`,
+    /// like extra columns of a table whose schema is older
+    pub fn set_unknown_column_policy(&mut self, unknown_column: UnknownColumn) {
+        self.unknown_column = unknown_column;
+    }
+
+    /// Add an attribute computed from the parsed ones, and from computed ones added before it.
+    /// It is evaluated in `Record::new` before the group id is hashed, so it can be grouped by.
+    /// `compute` should return `Attr::Null` when its source attributes are missing
+    pub fn add_computed(
+        &mut self,
+        attr_name: &str,
+        group_by: Option<ComponentRule>,
+        compute: impl Fn(&IndexMap<String, Attr>) -> Attr + 'static,
+    ) {
+        self.computed.push((attr_name.into(), Box::new(compute)));
+        if let Some(group_by_component) = group_by {
+            self.group_by.insert(attr_name.into(), group_by_component);
+        }
+    }
+}
+
+/// A group is a set of Record with same `group_id`s. Records are never changed, so here stores
+/// `Record`s references
+#[derive(Clone)]
+pub struct Group<'a> {
+    records: HashSet<ByAddress<&'a Record>>,
+    id: u64,
+    key: Vec<(String, Attr)>,
+}
+
+impl<'a> Group<'a> {
+    fn new(id: u64, key: Vec<(String, Attr)>) -> Self {
+        Self {
+            records: HashSet::new(),
+            id,
+            key,
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Group-by attributes and their representative values, like the start of an interval
+    pub fn key(&self) -> &[(String, Attr)] {
+        &self.key
+    }
+
+    /// Records in arbitrary order
+    pub fn records(&self) -> impl Iterator<Item = &'a Record> + '_ {
+        self.records.iter().map(|record| record.0)
+    }
+
+    /// Records of both groups, under the id and key of `self`
+    pub fn union(&self, other: &Self) -> Self {
+        let mut group = self.clone();
+        group.records.extend(other.records.iter().copied());
+        group
+    }
+}
+
+/// Records of a group paired with the fold results of their windows, see `Collection::rolling`
+pub type Rolled<'a> = Vec<(&'a Record, Attr)>;
+
+/// A Collection is a set of groups, with full or part of records in them.
+#[derive(Clone)]
+pub struct Collection<'a> {
+    groups: HashMap<u64, Group<'a>>,
+}
+
+/// Builds a collection from records one at a time, e.g. as they are read. Only a reference to
+/// each record and a key per group are held, the records themselves are owned by the caller, so
+/// no vector of all records is needed in between
+#[derive(Default)]
+pub struct CollectionBuilder<'a> {
+    groups: HashMap<u64, Group<'a>>,
+}
+
+impl<'a> CollectionBuilder<'a> {
+    pub fn new() -> Self {
+        CollectionBuilder {
+            groups: HashMap::new(),
+        }
+    }
+
+    /// Add the record to its group, fails if the group id is taken by another group key
+    pub fn push(&mut self, record: &'a Record) -> Result<(), GroupCollision> {
+        Collection::insert(&mut self.groups, record, record.group_id, &record.group_key)
+    }
+
+    pub fn finish(self) -> Collection<'a> {
+        Collection {
+            groups: self.groups,
+        }
+    }
+}
+
+/// Collection which owns its records, so it can be returned from the function which read them,
+/// and outlive the vector they were in. Records are shared, cloning the collection or building
+/// one from the same `Arc`s doesn't copy them. Operations are done through the borrowed
+/// `Collection` of `collection`
+#[derive(Clone, Default)]
+pub struct OwnedCollection {
+    records: Vec<Arc<Record>>,
+}
+
+impl OwnedCollection {
+    /// Panics if two different group keys collide into the same group id, see `try_new`
+    pub fn new(records: Vec<Record>) -> Self {
+        Self::try_new(records).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_new(records: Vec<Record>) -> Result<Self, GroupCollision> {
+        Self::try_from_shared(records.into_iter().map(Arc::new).collect())
+    }
+
+    /// Verify the group keys of records which may be shared with other collections
+    pub fn try_from_shared(records: Vec<Arc<Record>>) -> Result<Self, GroupCollision> {
+        Collection::try_new(records.iter().map(|record| &**record).collect())?;
+        Ok(OwnedCollection { records })
+    }
+
+    /// Group the records, which are verified not to collide already. They are grouped at each
+    /// call, by the group ids kept in them, so no attribute is hashed again
+    pub fn collection(&self) -> Collection<'_> {
+        Collection::new(self.records.iter().map(|record| &**record).collect())
+    }
+
+    /// Records in the order they were given
+    pub fn records(&self) -> &[Arc<Record>] {
+        &self.records
+    }
+}
+
+/// Copy the records of a collection, like the result of a filter, so it no longer borrows them.
+/// Records are in the order of `Collection::records`
+impl From<&Collection<'_>> for OwnedCollection {
+    fn from(collection: &Collection<'_>) -> Self {
+        OwnedCollection {
+            records: collection
+                .records()
+                .into_iter()
+                .map(|record| Arc::new(record.clone()))
+                .collect(),
+        }
+    }
+}
+
+/// Error emitted when different group keys are hashed into the same group id
+#[derive(PartialEq, Debug)]
+pub struct GroupCollision {
+    pub id: u64,
+    pub key: Vec<(String, Attr)>,
+    pub other_key: Vec<(String, Attr)>,
+}
+
+impl fmt::Display for GroupCollision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Error: group keys {:?} and {:?} collide into the same group id `{}'",
+            self.key, self.other_key, self.id
+        )
+    }
+}
+
+impl error::Error for GroupCollision {}
+
+/// Comparison operator of `Predicate::Cmp`, `Ne`, `Le` and `Ge` can't be told by an `Ordering`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CmpOp {
+    /// Whether a value which compares to another as `ord` satisfies the operator
+    pub fn holds(self, ord: Ordering) -> bool {
+        match self {
+            CmpOp::Eq => ord == Ordering::Equal,
+            CmpOp::Ne => ord != Ordering::Equal,
+            CmpOp::Lt => ord == Ordering::Less,
+            CmpOp::Le => ord != Ordering::Greater,
+            CmpOp::Gt => ord == Ordering::Greater,
+            CmpOp::Ge => ord != Ordering::Less,
+        }
+    }
+}
+
+/// The operator which only holds for `ord`, like `Lt` for `Ordering::Less`
+impl From<Ordering> for CmpOp {
+    fn from(ord: Ordering) -> Self {
+        match ord {
+            Ordering::Less => CmpOp::Lt,
+            Ordering::Equal => CmpOp::Eq,
+            Ordering::Greater => CmpOp::Gt,
+        }
+    }
+}
+
+impl fmt::Display for CmpOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            CmpOp::Eq => "=",
+            CmpOp::Ne => "!=",
+            CmpOp::Lt => "<",
+            CmpOp::Le => "<=",
+            CmpOp::Gt => ">",
+            CmpOp::Ge => ">=",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+/// Single comparison condition, it's the `Predicate::Cmp` leaf
+pub struct FilterCond {
+    attr_name: String,
+    val: Attr,
+    op: CmpOp,
+}
+
+/// Filter condition tree evaluated against each record
+pub enum Predicate {
+    /// Matches if the attribute compares to `val` as `op` tells, missing or `Null` attributes
+    /// never match, neither does NaN, on either side, even with `CmpOp::Ne`. Numeric values are
+    /// compared by value whatever their types are, so an `Int` column is compared to `Float(2.5)`
+    Cmp {
+        attr_name: String,
+        val: Attr,
+        op: CmpOp,
+    },
+    /// String predicates only match `Str` attributes, case-insensitive ones compare lowercased
+    /// strings
+    Contains {
+        attr_name: String,
+        pattern: String,
+        case_insensitive: bool,
+    },
+    StartsWith {
+        attr_name: String,
+        pattern: String,
+        case_insensitive: bool,
+    },
+    EndsWith {
+        attr_name: String,
+        pattern: String,
+        case_insensitive: bool,
+    },
+    /// Matches if the attribute is between `low` and `high`, each bound is included if its
+    /// flag of `inclusive`, `(low, high)`, is set. Values are compared like `Cmp`, so missing,
+    /// `Null` and NaN attributes never match
+    Between {
+        attr_name: String,
+        low: Attr,
+        high: Attr,
+        inclusive: (bool, bool),
+    },
+    /// Matches if the attribute is one of `values`, like `userid IN (3, 7, 9)`. Values are looked
+    /// up by hash, floats by their bit patterns. Missing or `Null` attributes never match
+    In {
+        attr_name: String,
+        values: HashSet<Attr>,
+    },
+    And(Box<Predicate>, Box<Predicate>),
+    Or(Box<Predicate>, Box<Predicate>),
+    Not(Box<Predicate>),
+}
+
+impl Predicate {
+    pub fn matches(&self, record: &Record) -> bool {
+        match self {
+            Predicate::Cmp { attr_name, val, op } => match record.attrs.get(attr_name) {
+                None | Some(Attr::Null) => false,
+                Some(attr) => attr.cmp_value(val).is_some_and(|ord| op.holds(ord)),
+            },
+            Predicate::Between {
+                attr_name,
+                low,
+                high,
+                inclusive: (low_inclusive, high_inclusive),
+            } => match record.attrs.get(attr_name) {
+                None | Some(Attr::Null) => false,
+                Some(attr) => {
+                    let above = if *low_inclusive { CmpOp::Ge } else { CmpOp::Gt };
+                    let below = if *high_inclusive {
+                        CmpOp::Le
+                    } else {
+                        CmpOp::Lt
+                    };
+                    attr.cmp_value(low).is_some_and(|ord| above.holds(ord))
+                        && attr.cmp_value(high).is_some_and(|ord| below.holds(ord))
+                }
+            },
+            Predicate::In { attr_name, values } => match record.attrs.get(attr_name) {
+                None | Some(Attr::Null) => false,
+                Some(attr) => values.contains(attr),
+            },
+            Predicate::Contains {
+                attr_name,
+                pattern,
+                case_insensitive,
+            } => Self::str_matches(record, attr_name, pattern, *case_insensitive, |x, y| {
+                x.contains(y)
+            }),
+            Predicate::StartsWith {
+                attr_name,
+                pattern,
+                case_insensitive,
+            } => Self::str_matches(record, attr_name, pattern, *case_insensitive, |x, y| {
+                x.starts_with(y)
+            }),
+            Predicate::EndsWith {
+                attr_name,
+                pattern,
+                case_insensitive,
+            } => Self::str_matches(record, attr_name, pattern, *case_insensitive, |x, y| {
+                x.ends_with(y)
+            }),
+            Predicate::And(lhs, rhs) => lhs.matches(record) && rhs.matches(record),
+            Predicate::Or(lhs, rhs) => lhs.matches(record) || rhs.matches(record),
+            Predicate::Not(predicate) => !predicate.matches(record),
+        }
+    }
+
+    fn str_matches(
+        record: &Record,
+        attr_name: &str,
+        pattern: &str,
+        case_insensitive: bool,
+        f: impl Fn(&str, &str) -> bool,
+    ) -> bool {
+        match record.attrs.get(attr_name) {
+            Some(Attr::Str(v)) if case_insensitive => f(&v.to_lowercase(), &pattern.to_lowercase()),
+            Some(Attr::Str(v)) => f(v, pattern),
+            _ => false,
+        }
+    }
+}
+
+/// SQL-like condition, like `i > 200 AND s LIKE 'a%'`, displayed for fold operations
+impl fmt::Display for Predicate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let like = |f: &mut fmt::Formatter, attr_name, pattern, case_insensitive: &bool| {
+            let op = if *case_insensitive { "ILIKE" } else { "LIKE" };
+            write!(f, "{} {} '{}'", attr_name, op, pattern)
+        };
+        match self {
+            Predicate::Cmp { attr_name, val, op } => write!(f, "{} {} {}", attr_name, op, val),
+            Predicate::Between {
+                attr_name,
+                low,
+                high,
+                inclusive: (low_inclusive, high_inclusive),
+            } => {
+                let op = |inclusive: &bool| if *inclusive { "<=" } else { "<" };
+                write!(
+                    f,
+                    "{} {} {} {} {}",
+                    low,
+                    op(low_inclusive),
+                    attr_name,
+                    op(high_inclusive),
+                    high
+                )
+            }
+            Predicate::In { attr_name, values } => {
+                let mut values: Vec<&Attr> = values.iter().collect();
+                values.sort();
+                let values: Vec<String> = values.iter().map(|attr| attr.to_string()).collect();
+                write!(f, "{} IN ({})", attr_name, values.join(", "))
+            }
+            Predicate::Contains {
+                attr_name,
+                pattern,
+                case_insensitive,
+            } => like(f, attr_name, format!("%{}%", pattern), case_insensitive),
+            Predicate::StartsWith {
+                attr_name,
+                pattern,
+                case_insensitive,
+            } => like(f, attr_name, format!("{}%", pattern), case_insensitive),
+            Predicate::EndsWith {
+                attr_name,
+                pattern,
+                case_insensitive,
+            } => like(f, attr_name, format!("%{}", pattern), case_insensitive),
+            Predicate::And(lhs, rhs) => write!(f, "({} AND {})", lhs, rhs),
+            Predicate::Or(lhs, rhs) => write!(f, "({} OR {})", lhs, rhs),
+            Predicate::Not(predicate) => write!(f, "NOT {}", predicate),
+        }
+    }
+}
+
+impl From<FilterCond> for Predicate {
+    fn from(filter_cond: FilterCond) -> Self {
+        Predicate::Cmp {
+            attr_name: filter_cond.attr_name,
+            val: filter_cond.val,
+            op: filter_cond.op,
+        }
+    }
+}
+
+impl<'a> Collection<'a> {
+    /// Panics if two different group keys collide into the same group id, see `try_new`
+    pub fn new(records: Vec<&'a Record>) -> Self {
+        Self::try_new(records).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Group records by their group ids, verifying that records of the same group id really share
+    /// the same group key
+    pub fn try_new(records: Vec<&'a Record>) -> Result<Self, GroupCollision> {
+        let mut builder = CollectionBuilder::new();
+        for record in records {
+            builder.push(record)?;
+        }
+        Ok(builder.finish())
+    }
+
+    /// Same as `new`, records are grouped in parallel
+    #[cfg(feature = "parallel")]
+    pub fn par_new(records: Vec<&'a Record>) -> Self {
+        Self::try_par_new(records).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Same as `try_new`, records are grouped into a shard per thread, then shards are merged
+    #[cfg(feature = "parallel")]
+    pub fn try_par_new(records: Vec<&'a Record>) -> Result<Self, GroupCollision> {
+        use rayon::prelude::*;
+
+        let groups = records
+            .into_par_iter()
+            .try_fold(HashMap::new, |mut shard, record| {
+                Self::insert(&mut shard, record, record.group_id, &record.group_key)?;
+                Ok(shard)
+            })
+            .try_reduce(HashMap::new, |mut groups, shard| {
+                for (id, group) in shard {
+                    match groups.entry(id) {
+                        Entry::Vacant(entry) => {
+                            entry.insert(group);
+                        }
+                        Entry::Occupied(mut entry) => {
+                            let merged: &mut Group = entry.get_mut();
+                            if merged.key != group.key {
+                                return Err(GroupCollision {
+                                    id,
+                                    key: merged.key.clone(),
+                                    other_key: group.key,
+                                });
+                            }
+                            merged.records.extend(group.records);
+                        }
+                    }
+                }
+                Ok(groups)
+            })?;
+        Ok(Self { groups })
+    }
+
+    /// Add the record to the group of `group_id`, which is hashed from `group_key`
+    fn insert(
+        groups: &mut HashMap<u64, Group<'a>>,
+        record: &'a Record,
+        group_id: u64,
+        group_key: &[(String, Attr)],
+    ) -> Result<(), GroupCollision> {
+        let group = groups
+            .entry(group_id)
+            .or_insert_with(|| Group::new(group_id, group_key.to_vec()));
+        if group.key != group_key {
+            return Err(GroupCollision {
+                id: group_id,
+                key: group.key.clone(),
+                other_key: group_key.to_vec(),
+            });
+        }
+        group.records.insert(ByAddress(record));
+        Ok(())
+    }
+
+    /// Stack the records of all collections, like appending a new day's records. Groups of the
+    /// same id are merged, and fail if their keys differ, which happens when the collections are
+    /// grouped by different rules. Groups hold records by address, so a record in more than one
+    /// collection is kept once, while value-equal records parsed from different rows are all kept
+    pub fn concat(collections: &[Collection<'a>]) -> Result<Collection<'a>, GroupCollision> {
+        let mut groups: HashMap<u64, Group<'a>> = HashMap::new();
+        for group in collections
+            .iter()
+            .flat_map(|collection| collection.groups.values())
+        {
+            match groups.entry(group.id) {
+                Entry::Vacant(entry) => {
+                    entry.insert(group.to_owned());
+                }
+                Entry::Occupied(mut entry) => {
+                    let merged: &mut Group = entry.get_mut();
+                    if merged.key != group.key {
+                        return Err(GroupCollision {
+                            id: group.id,
+                            key: merged.key.clone(),
+                            other_key: group.key.clone(),
+                        });
+                    }
+                    merged.records.extend(group.records.iter().copied());
+                }
+            }
+        }
+        Ok(Collection { groups })
+    }
+
+    /// Group the records again by the rules of `ctx`, they are neither parsed nor computed again,
+    /// so the group-by attributes should be in the records already. The records themselves keep
+    /// their own group ids
+    pub fn regroup(&self, ctx: &Ctx) -> Result<Collection<'a>, Error> {
+        let mut groups = HashMap::new();
+        for record in self.groups.values().flat_map(|group| group.records()) {
+            let (group_id, group_key) = Record::group_of(ctx, &record.attrs)?;
+            Self::insert(&mut groups, record, group_id, &group_key)?;
+        }
+        Ok(Collection { groups })
+    }
+
+    /// Transform every record into a new one, like scaling an attribute, in the order of
+    /// `records`. The new records are grouped by their own group ids, which are computed when
+    /// they're built, e.g. by `Record::from_typed`, so a transform changing a group-by attribute
+    /// moves the record to another group. Fails with the first failure of `f`, or when group ids
+    /// collide
+    pub fn map<F: Fn(&Record) -> Result<Record, Error>>(
+        &self,
+        f: F,
+    ) -> Result<OwnedCollection, Error> {
+        let records = self
+            .records()
+            .into_iter()
+            .map(f)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(OwnedCollection::try_new(records)?)
+    }
+
+    /// Groups in arbitrary order
+    pub fn groups(&self) -> impl Iterator<Item = &Group<'a>> {
+        self.groups.values()
+    }
+
+    /// Groups ordered by `f`, groups of equal keys are ordered by id, so the order is reproducible
+    pub fn groups_sorted_by<K: Ord, F: Fn(&Group) -> K>(&self, f: F) -> Vec<&Group<'a>> {
+        let mut groups: Vec<&Group> = self.groups.values().collect();
+        groups.sort_by(|x, y| f(x).cmp(&f(y)).then_with(|| x.id.cmp(&y.id)));
+        groups
+    }
+
+    /// Groups ordered by their group keys, which are compared attribute by attribute in the order
+    /// of attribute names
+    pub fn groups_sorted_by_key(&self) -> Vec<&Group<'a>> {
+        self.groups_sorted_by(|group| group.key.clone())
+    }
+
+    pub fn group_count(&self) -> usize {
+        self.groups.len()
+    }
+
+    pub fn record_count(&self) -> usize {
+        self.groups.values().map(|group| group.records.len()).sum()
+    }
+
+    /// Check the invariants operations keep: no group is empty, and every group is keyed by its
+    /// own id. It's cheap, for debugging and tests
+    pub fn validate(&self) -> Result<(), Error> {
+        let mut ids: Vec<&u64> = self.groups.keys().collect();
+        ids.sort();
+        for id in ids {
+            let group = &self.groups[id];
+            if group.id != *id {
+                return Err(Error::InvalidCollection(format!(
+                    "group `{}' is keyed by `{}'",
+                    group.id, id
+                )));
+            }
+            if group.records.is_empty() {
+                return Err(Error::InvalidCollection(format!(
+                    "group `{}' has no record",
+                    id
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Record count of each group keyed by group id, like `COUNT` without a `FoldResult`
+    pub fn size_by_group(&self) -> HashMap<u64, usize> {
+        self.groups
+            .values()
+            .map(|group| (group.id, group.records.len()))
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    /// Filter the collection with predicate, generate new collection
+    pub fn filter_records(self, predicate: impl Into<Predicate>) -> Self {
+        let predicate = predicate.into();
+        self.filter_with(|record| predicate.matches(record))
+    }
+
+    /// Keep the records which `pred` holds for, groups left empty are dropped
+    pub fn filter_with<F: Fn(&Record) -> bool>(mut self, pred: F) -> Self {
+        self.groups.retain(|_, group| {
+            group.records.retain(|record| pred(record));
+            !group.records.is_empty()
+        });
+        self
+    }
+
+    /// Split the records into the ones which `pred` holds for and the others in a single pass, like
+    /// `filter_with` along with its complement. Groups left empty are dropped on either side
+    pub fn split_by<F: Fn(&Record) -> bool>(self, pred: F) -> (Self, Self) {
+        let mut matched = HashMap::new();
+        let mut unmatched = HashMap::new();
+        for (id, group) in self.groups {
+            let (yes, no): (HashSet<_>, HashSet<_>) =
+                group.records.into_iter().partition(|record| pred(record));
+            for (groups, records) in [(&mut matched, yes), (&mut unmatched, no)] {
+                if !records.is_empty() {
+                    let key = group.key.clone();
+                    groups.insert(id, Group { records, id, key });
+                }
+            }
+        }
+        (
+            Collection { groups: matched },
+            Collection { groups: unmatched },
+        )
+    }
+
+    /// Keep the groups which `pred` holds for, like the groups having enough records
+    pub fn retain_groups<F: Fn(&Group) -> bool>(mut self, pred: F) -> Self {
+        self.groups.retain(|_, group| pred(group));
+        self
+    }
+
+    /// Keep about `fraction` of the records of each group at random, groups left empty are
+    /// dropped. The same `seed` picks the same records of the same collection, as records of a
+    /// group are drawn in the order of their attributes, by a generator seeded per group
+    pub fn sample(mut self, fraction: f64, seed: u64) -> Self {
+        self.groups.retain(|id, group| {
+            let mut rng = StdRng::seed_from_u64(seed ^ id);
+            let mut records: Vec<&Record> = group.records().collect();
+            records.sort_by(|x, y| x.attrs.values().cmp(y.attrs.values()));
+            group.records = records
+                .into_iter()
+                .filter(|_| rng.random::<f64>() < fraction)
+                .map(ByAddress)
+                .collect();
+            !group.records.is_empty()
+        });
+        self
+    }
+
+    /// Keep at most `n` records of each group, in the order of `sorted_records`
+    pub fn top_n(mut self, attr_name: &str, n: usize, ascending: bool) -> Self {
+        self.groups = self
+            .groups
+            .into_iter()
+            .filter_map(|(id, group)| {
+                let mut records: Vec<&Record> = group.records().collect();
+                records.sort_by(|x, y| Record::cmp_by(x, y, attr_name, ascending));
+                let records: HashSet<ByAddress<&Record>> =
+                    records.into_iter().take(n).map(ByAddress).collect();
+                if records.is_empty() {
+                    None
+                } else {
+                    Some((
+                        id.to_owned(),
+                        Group {
+                            records,
+                            id: id.to_owned(),
+                            key: group.key,
+                        },
+                    ))
+                }
+            })
+            .collect();
+        self
+    }
+
+    pub fn intersection(mut self, other: &Self) -> Self {
+        self.groups.retain(|id, group| match other.groups.get(id) {
+            Some(other_group) => {
+                group
+                    .records
+                    .retain(|record| other_group.records.contains(record));
+                !group.records.is_empty()
+            }
+            None => false,
+        });
+        self
+    }
+
+    pub fn union(self, other: &Self) -> Self {
+        self.merge_with(other, Group::union)
+    }
+
+    /// Groups of both collections, `f` combines the two groups of an id found in both, like
+    /// `Group::union`. The combined group is kept under that id, or dropped when it's empty, so
+    /// `f` must keep the id, e.g. by cloning or filtering either group
+    pub fn merge_with<F: Fn(&Group<'a>, &Group<'a>) -> Group<'a>>(
+        mut self,
+        other: &Self,
+        f: F,
+    ) -> Self {
+        other.groups.iter().for_each(|(id, other_group)| {
+            if let Some(group) = self.groups.get_mut(id) {
+                *group = f(group, other_group);
+                debug_assert_eq!(group.id, *id, "merged group should keep its id");
+                if group.records.is_empty() {
+                    self.groups.remove(id);
+                }
+            } else {
+                self.groups.insert(id.to_owned(), other_group.to_owned());
+            }
+        });
+        self
+    }
+
+    pub fn difference(mut self, other: &Self) -> Self {
+        self.groups.retain(|id, group| match other.groups.get(id) {
+            Some(other_group) => {
+                group
+                    .records
+                    .retain(|record| !other_group.records.contains(record));
+                !group.records.is_empty()
+            }
+            // Nothing to subtract from the group
+            None => true,
+        });
+        self
+    }
+
+    /// Records in exactly one of the two collections
+    pub fn symmetric_difference(mut self, other: &Self) -> Self {
+        other.groups.iter().for_each(|(id, other_group)| {
+            if let Some(group) = self.groups.get_mut(id) {
+                other_group.records.iter().for_each(|record| {
+                    if !group.records.remove(record) {
+                        group.records.insert(*record);
+                    }
+                });
+                if group.records.is_empty() {
+                    self.groups.remove(id);
+                }
+            } else {
+                self.groups.insert(id.to_owned(), other_group.to_owned());
+            }
+        });
+        self
+    }
+
+    /// Whether every record is in `other`, a group absent from `other` is not contained
+    pub fn is_subset(&self, other: &Self) -> bool {
+        self.groups.iter().all(|(id, group)| {
+            other
+                .groups
+                .get(id)
+                .is_some_and(|other_group| group.records.is_subset(&other_group.records))
+        })
+    }
+
+    pub fn is_superset(&self, other: &Self) -> bool {
+        other.is_subset(self)
+    }
+
+    /// Records of each group sorted by the attribute, groups are ordered by id. Records missing the
+    /// attribute, or with a `Null` one, are at the end in both directions. Ties are broken by all
+    /// attributes in order, so the result is reproducible
+    pub fn sorted_records(&self, attr_name: &str, ascending: bool) -> Vec<(u64, Vec<&Record>)> {
+        let mut groups: Vec<(u64, Vec<&Record>)> = self
+            .groups
+            .values()
+            .map(|group| {
+                let mut records: Vec<&Record> = group.records().collect();
+                records.sort_by(|x, y| Record::cmp_by(x, y, attr_name, ascending));
+                (group.id, records)
+            })
+            .collect();
+        groups.sort_by_key(|(id, _)| *id);
+        groups
+    }
+
+    /// Sorted unique values of the attribute in each group, keyed by group id. Records missing
+    /// the attribute, or with a `Null` one, are skipped, so a group can have no value at all
+    pub fn distinct_values(&self, attr_name: &str) -> HashMap<u64, Vec<Attr>> {
+        self.groups
+            .values()
+            .map(|group| {
+                let values: BTreeSet<&Attr> = group
+                    .records()
+                    .filter_map(|record| record.attrs.get(attr_name))
+                    .filter(|attr| !matches!(attr, Attr::Null))
+                    .collect();
+                (group.id, values.into_iter().cloned().collect())
+            })
+            .collect()
+    }
+
+    /// Fold a moving window of every record, groups are ordered by id and records of a group by
+    /// `order_by` ascending. The window of a record whose `order_by` value is `v` holds the
+    /// records of its group valued in `[v + start, v + start + step)`, so
+    /// `Interval::new(-6, 7)` over a day column is a trailing 7-day window. `DateTime` values are
+    /// seconds since Unix epoch
+    ///
+    /// Windows at the edges of a group are partial, they only hold the records there are.
+    /// Records whose `order_by` attribute is missing, `Null` or non-numeric are left out
+    pub fn rolling(
+        &self,
+        order_by: &str,
+        window: Interval,
+        op: FoldOperation,
+    ) -> Result<Vec<(u64, Rolled<'a>)>, FoldError> {
+        fn order_key(record: &Record, order_by: &str) -> Option<f64> {
+            match record.attrs.get(order_by)? {
+                Attr::DateTime(v) => Some(v.and_utc().timestamp() as f64),
+                attr => attr.as_f64().filter(|v| !v.is_nan()),
+            }
+        }
+        let ops = [op];
+        let mut groups: Vec<&Group> = self.groups.values().collect();
+        groups.sort_by_key(|group| group.id);
+        groups
+            .into_iter()
+            .map(|group| {
+                let mut records: Vec<(f64, &Record)> = group
+                    .records()
+                    .filter_map(|record| Some((order_key(record, order_by)?, record)))
+                    .collect();
+                records.sort_by(|(v, x), (w, y)| {
+                    v.total_cmp(w)
+                        .then_with(|| Record::cmp_by(x, y, order_by, true))
+                });
+                let rolled = records
+                    .iter()
+                    .map(|&(v, record)| {
+                        let from = v + window.start as f64;
+                        let to = from + window.step as f64;
+                        let lo = records.partition_point(|&(x, _)| x < from);
+                        let hi = records.partition_point(|&(x, _)| x < to);
+                        let windowed = records[lo..hi.max(lo)].iter().map(|&(_, x)| x);
+                        Ok((record, fold_records(windowed, &ops)?.remove(0)))
+                    })
+                    .collect::<Result<Vec<_>, FoldError>>()?;
+                Ok((group.id, rolled))
+            })
+            .collect()
+    }
+
+    /// Spread `agg` of `value` into a table, which has a row per distinct `index` value and a
+    /// column per distinct `columns` value. Each cell folds the records having both values,
+    /// across all groups, and it's `Null` when there is no such record. Records missing `index`
+    /// or `columns` are left out
+    ///
+    /// `agg` should fold `value`, like `SUM("amount")` for `"amount"`, other attributes are
+    /// rejected. `COUNT` and `COUNT_IF` fold no attribute, so they're taken with any `value`
+    pub fn pivot(
+        &self,
+        index: &str,
+        columns: &str,
+        value: &str,
+        agg: FoldOperation,
+    ) -> Result<PivotTable, Error> {
+        if let Some(attr_name) = agg.attr_name().filter(|&attr_name| attr_name != value) {
+            return Err(Error::PivotValue {
+                value: value.into(),
+                attr_name: attr_name.into(),
+            });
+        }
+        let mut cells: HashMap<(&Attr, &Attr), Vec<&Record>> = HashMap::new();
+        for record in self.groups.values().flat_map(|group| group.records()) {
+            if let (Some(row), Some(column)) = (record.attrs.get(index), record.attrs.get(columns))
+            {
+                cells.entry((row, column)).or_default().push(record);
+            }
+        }
+        let mut index: Vec<Attr> = cells.keys().map(|&(row, _)| row.to_owned()).collect();
+        index.sort();
+        index.dedup();
+        let mut columns: Vec<Attr> = cells.keys().map(|&(_, column)| column.to_owned()).collect();
+        columns.sort();
+        columns.dedup();
+        let cells = index
+            .iter()
+            .map(|row| {
+                columns
+                    .iter()
+                    .map(|column| match cells.get(&(row, column)) {
+                        Some(records) => Ok(fold_records(
+                            records.iter().copied(),
+                            std::slice::from_ref(&agg),
+                        )?
+                        .remove(0)),
+                        None => Ok(Attr::Null),
+                    })
+                    .collect()
+            })
+            .collect::<Result<_, FoldError>>()?;
+        Ok(PivotTable {
+            index,
+            columns,
+            cells,
+        })
+    }
+
+    /// Keep a single record of the value-equal ones in each group. Groups hold records by address,
+    /// so two records parsed from identical rows are both kept until they are deduplicated here
+    pub fn dedup_by_value(mut self) -> Self {
+        self.groups.values_mut().for_each(|group| {
+            let mut seen: HashSet<&Record> = HashSet::new();
+            group.records.retain(|record| seen.insert(record.0));
+        });
+        self
+    }
+
+    /// Keep the groups whose fold result satisfies `cmp` against `threshold`, like SQL's
+    /// `HAVING`. Numeric results are compared by value whatever their types are, so
+    /// `Attr::Int(1000)` works as a threshold for `SUM`. A group folding to `Null` is always
+    /// dropped
+    pub fn filter_groups_by_fold(
+        mut self,
+        op: FoldOperation,
+        cmp: CmpOp,
+        threshold: Attr,
+    ) -> Result<Self, FoldError> {
+        let kept: HashSet<u64> = self
+            .fold(op)?
+            .result
+            .into_iter()
+            .filter(|(_, result)| {
+                let ord = match (result.as_f64(), threshold.as_f64()) {
+                    _ if *result == Attr::Null => None,
+                    (Some(x), Some(y)) => x.partial_cmp(&y),
+                    _ => result.partial_cmp(&threshold),
+                };
+                ord.is_some_and(|ord| cmp.holds(ord))
+            })
+            .map(|(group, _)| group.id)
+            .collect();
+        self.groups.retain(|id, _| kept.contains(id));
+        Ok(self)
+    }
+
+    /// All records of the groups, ordered by group id, then by all their attributes in a group
+    pub fn records(&self) -> Vec<&'a Record> {
+        let mut groups: Vec<&Group> = self.groups.values().collect();
+        groups.sort_by_key(|group| group.id);
+        groups
+            .into_iter()
+            .flat_map(|group| {
+                let mut records: Vec<&Record> = group.records().collect();
+                records.sort_by(|x, y| x.attrs.values().cmp(y.attrs.values()));
+                records
+            })
+            .collect()
+    }
+
+    /// Project every record with `Record::project`, in the order of `records`
+    pub fn project(&self, names: &[&str]) -> Vec<IndexMap<String, &Attr>> {
+        self.records()
+            .into_iter()
+            .map(|record| record.project(names))
+            .collect()
+    }
+
+    /// Inner join with `other` on the `on` attribute, records match when their values are equal
+    /// as `Attr`. Records missing the attribute, or with a `Null` one, on either side never match.
+    ///
+    /// Attributes of the left record come first, a right attribute whose name is already taken is
+    /// renamed with a `_right` suffix, except `on` itself which is kept once. Joined records are
+    /// sorted by their attributes, so the result is reproducible
+    pub fn inner_join(&self, other: &Self, on: &str) -> Vec<JoinedRecord> {
+        let key_of = |record: &'a Record| record.attrs.get(on).filter(|&attr| *attr != Attr::Null);
+        let mut index: HashMap<&Attr, Vec<&Record>> = HashMap::new();
+        other
+            .groups
+            .values()
+            .flat_map(|group| group.records())
+            .for_each(|record| {
+                if let Some(key) = key_of(record) {
+                    index.entry(key).or_default().push(record);
+                }
+            });
+
+        let mut joined: Vec<JoinedRecord> = self
+            .groups
+            .values()
+            .flat_map(|group| group.records())
+            .filter_map(|left| Some((left, index.get(key_of(left)?)?)))
+            .flat_map(|(left, rights)| {
+                rights.iter().map(move |right| {
+                    let mut attrs = left.attrs.clone();
+                    right
+                        .attrs
+                        .iter()
+                        .filter(|(name, _)| name.as_str() != on)
+                        .for_each(|(name, attr)| {
+                            let name = if attrs.contains_key(name) {
+                                format!("{}_right", name)
+                            } else {
+                                name.to_owned()
+                            };
+                            attrs.insert(name, attr.clone());
+                        });
+                    JoinedRecord { attrs }
+                })
+            })
+            .collect();
+        joined.sort_by(|x, y| x.attrs.values().cmp(y.attrs.values()));
+        joined
+    }
+
+    /// Keep the records whose `on` attribute equals no `on` attribute of `other`, in any of its
+    /// groups. Unlike `difference`, records are matched by value instead of by address. Records
+    /// missing `on` or having it `Null` never match, so they are kept, like in `inner_join`
+    pub fn anti_join(&self, other: &Self, on: &str) -> Self {
+        let index: HashSet<&Attr> = other
+            .groups
+            .values()
+            .flat_map(|group| group.records())
+            .filter_map(|record| record.attrs.get(on))
+            .filter(|&attr| *attr != Attr::Null)
+            .collect();
+        self.clone().filter_with(|record| {
+            record
+                .attrs
+                .get(on)
+                .is_none_or(|attr| !index.contains(attr))
+        })
+    }
+
+    /// Write records as csv, columns are in the order of `headers`, missing attributes are written
+    /// as empty cells. Groups are ordered by id and records in a group by their cells, so the
+    /// output is reproducible
+    pub fn write_csv<W: io::Write>(&self, wtr: W, headers: &[&str]) -> Result<(), csv::Error> {
+        self.write_csv_with_precision(wtr, headers, None)
+    }
+
+    /// `write_csv` with floats rounded to `precision` decimals, when it's given
+    pub fn write_csv_with_precision<W: io::Write>(
+        &self,
+        wtr: W,
+        headers: &[&str],
+        precision: Option<usize>,
+    ) -> Result<(), csv::Error> {
+        let mut wtr = csv::Writer::from_writer(wtr);
+        wtr.write_record(headers)?;
+        for row in self
+            .rendered_groups(headers, precision)
+            .into_iter()
+            .flatten()
+        {
+            wtr.write_record(row)?;
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+
+    /// Records as a json array of objects, mapping attribute names to values in the order of
+    /// headers. Records are in the order of `records`
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> serde_json::Value {
+        self.records()
+            .into_iter()
+            .map(|record| {
+                record
+                    .attrs()
+                    .map(|(name, attr)| (name.to_owned(), attr.to_json()))
+                    .collect::<serde_json::Map<_, _>>()
+            })
+            .collect()
+    }
+
+    /// Render records as a bordered table for reading, columns are in the order of `headers` and
+    /// aligned to their widest cells, groups are separated by a line. Rows are in the order of
+    /// `write_csv`
+    pub fn to_ascii_table(&self, headers: &[&str]) -> String {
+        self.to_ascii_table_with_precision(headers, None)
+    }
+
+    /// `to_ascii_table` with floats rounded to `precision` decimals, when it's given
+    pub fn to_ascii_table_with_precision(
+        &self,
+        headers: &[&str],
+        precision: Option<usize>,
+    ) -> String {
+        let groups = self.rendered_groups(headers, precision);
+        let mut widths: Vec<usize> = headers
+            .iter()
+            .map(|header| header.chars().count())
+            .collect();
+        groups.iter().flatten().for_each(|row| {
+            zip(widths.iter_mut(), row).for_each(|(width, cell)| {
+                *width = (*width).max(cell.chars().count());
+            })
+        });
+
+        let line = |fill: char| -> String {
+            let cells: Vec<String> = widths
+                .iter()
+                .map(|width| fill.to_string().repeat(width + 2))
+                .collect();
+            format!("+{}+\n", cells.join("+"))
+        };
+        let row = |cells: &[&str]| -> String {
+            let cells: Vec<String> = zip(&widths, cells)
+                .map(|(width, cell)| format!(" {:<width$} ", cell, width = width))
+                .collect();
+            format!("|{}|\n", cells.join("|"))
+        };
+
+        let mut table = line('-') + &row(headers) + &line('=');
+        for (i, group) in groups.iter().enumerate() {
+            if i > 0 {
+                table += &line('-');
+            }
+            for cells in group {
+                let cells: Vec<&str> = cells.iter().map(|cell| cell.as_str()).collect();
+                table += &row(&cells);
+            }
+        }
+        if !groups.is_empty() {
+            table += &line('-');
+        }
+        table
+    }
+
+    /// Cells of records in the order of `headers`, missing attributes are empty cells. Groups are
+    /// ordered by id and records in a group by their cells
+    fn rendered_groups(&self, headers: &[&str], precision: Option<usize>) -> Vec<Vec<Vec<String>>> {
+        let mut groups: Vec<&Group> = self.groups.values().collect();
+        groups.sort_by_key(|group| group.id);
+        groups
+            .into_iter()
+            .map(|group| {
+                let mut rows: Vec<Vec<String>> = group
+                    .records
+                    .iter()
+                    .map(|record| {
+                        headers
+                            .iter()
+                            .map(|&header| {
+                                record.attrs.get(header).map_or_else(String::new, |attr| {
+                                    attr.to_string_with_precision(precision)
+                                })
+                            })
+                            .collect()
+                    })
+                    .collect();
+                rows.sort();
+                rows
+            })
+            .collect()
+    }
+
+    /// Count, mean, standard deviation, min, quartiles and max of the attribute in each group,
+    /// keyed by group id, like `describe()` of pandas. Missing and `Null` attributes are skipped
+    /// as numeric folds do, other non-numeric ones fail with a `DESCRIBE` fold error
+    pub fn describe(&self, attr_name: &str) -> Result<HashMap<u64, Describe>, FoldError> {
+        self.groups
+            .values()
+            .map(|group| {
+                let mut values = Vec::new();
+                for record in group.records() {
+                    match record.attrs.get(attr_name) {
+                        None | Some(Attr::Null) => (),
+                        Some(attr) => values.push(attr.as_f64().ok_or_else(|| FoldError {
+                            op: "DESCRIBE",
+                            attr_name: attr_name.into(),
+                        })?),
+                    }
+                }
+                values.sort_by(f64::total_cmp);
+                Ok((group.id, Describe::new(&values)))
+            })
+            .collect()
+    }
+
+    /// Fold the collection with a single operation, fails when a numeric fold meets a
+    /// non-numeric attribute
+    pub fn fold(&self, op: FoldOperation) -> Result<FoldResult<'_>, FoldError> {
+        Ok(self.fold_many(vec![op])?.remove(0))
+    }
+
+    /// Fold the collection with all operations at once, each group's records are walked exactly
+    /// once. Stops at the first record which can't be folded by some operation
+    pub fn fold_many(&self, ops: Vec<FoldOperation>) -> Result<Vec<FoldResult<'_>>, FoldError> {
+        let folded = self
+            .groups
+            .values()
+            .map(|group| Ok((group, fold_group(group, &ops)?)))
+            .collect::<Result<Vec<_>, FoldError>>()?;
+        Ok(self.fold_results(ops, folded))
+    }
+
+    /// Same as `fold_many`, groups are folded in parallel while records of a group are walked
+    /// sequentially
+    #[cfg(feature = "parallel")]
+    pub fn par_fold_many(&self, ops: Vec<FoldOperation>) -> Result<Vec<FoldResult<'_>>, FoldError> {
+        use rayon::prelude::*;
+
+        let folded = self
+            .groups
+            .par_iter()
+            .map(|(_, group)| Ok((group, fold_group(group, &ops)?)))
+            .collect::<Result<Vec<_>, FoldError>>()?;
+        Ok(self.fold_results(ops, folded))
+    }
+
+    /// Spread folded groups, each has a result per operation, into a fold result per operation
+    fn fold_results<'b>(
+        &'b self,
+        ops: Vec<FoldOperation>,
+        folded: Vec<(&'b Group<'b>, Vec<Attr>)>,
+    ) -> Vec<FoldResult<'b>> {
+        let mut results: Vec<HashMap<ByAddress<&Group>, Attr>> =
+            ops.iter().map(|_| HashMap::new()).collect();
+        for (group, attrs) in folded {
+            for (attr, result) in zip(attrs, results.iter_mut()) {
+                result.insert(ByAddress(group), attr);
+            }
+        }
+        zip(ops, results)
+            .map(|(fold_func, result)| FoldResult {
+                collection: ByAddress(self),
+                fold_func,
+                result,
+            })
+            .collect()
+    }
+}
+
+/// Fold a group with all operations, its records are walked once
+fn fold_group(group: &Group, ops: &[FoldOperation]) -> Result<Vec<Attr>, FoldError> {
+    fold_records(group.records(), ops)
+}
+
+fn fold_records<'a>(
+    records: impl Iterator<Item = &'a Record>,
+    ops: &[FoldOperation],
+) -> Result<Vec<Attr>, FoldError> {
+    let mut accumulators: Vec<Accumulator> = ops.iter().map(Accumulator::new).collect();
+    for record in records {
+        for (op, accumulator) in zip(ops, accumulators.iter_mut()) {
+            accumulator.push(op, record)?;
+        }
+    }
+    Ok(zip(ops, accumulators)
+        .map(|(op, accumulator)| accumulator.finish(op))
+        .collect())
+}
+
+/// Group-by attributes and their representative values, see `Group::key`
+pub type GroupKey = Vec<(String, Attr)>;
+
+/// Folds records one at a time as they're read, keeping a running aggregate per group rather
+/// than the records, so memory is bounded by the number of groups. Only folds which can be
+/// computed online are supported, `COUNT`, `COUNT_IF`, `SUM`, `AVG` and `RMS`. Numeric values
+/// are accumulated like `Collection::fold`, in f64, or as exact decimals for `SUM` and `AVG` of a
+/// group whose first value is a decimal
+pub struct StreamingFold {
+    op: FoldOperation,
+    groups: HashMap<u64, (GroupKey, RunningSum, i32)>,
+}
+
+/// Running sum of a group in `StreamingFold`, see `Accumulator::DecimalSum`
+#[derive(Clone, Copy)]
+enum RunningSum {
+    Float(f64),
+    #[cfg(feature = "decimal")]
+    Decimal(Decimal),
+}
+
+impl StreamingFold {
+    /// `None` if the fold needs all the values of a group, like `MODE` or `PERCENTILE`
+    pub fn new(op: FoldOperation) -> Option<Self> {
+        match op {
+            FoldOperation::COUNT
+            | FoldOperation::COUNT_IF(_)
+            | FoldOperation::SUM(_)
+            | FoldOperation::AVG(_)
+            | FoldOperation::RMS(_) => Some(StreamingFold {
+                op,
+                groups: HashMap::new(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Add the record to the aggregate of its group, fails if the group id is taken by another
+    /// group key, or the attribute is non-numeric
+    pub fn push(&mut self, record: &Record) -> Result<(), Error> {
+        let value = match &self.op {
+            FoldOperation::COUNT => Some(RunningSum::Float(1.0)),
+            FoldOperation::COUNT_IF(predicate) => {
+                Some(RunningSum::Float(if predicate.matches(record) {
+                    1.0
+                } else {
+                    0.0
+                }))
+            }
+            FoldOperation::SUM(attr_name) | FoldOperation::AVG(attr_name) => {
+                match record.attrs.get(attr_name) {
+                    #[cfg(feature = "decimal")]
+                    Some(Attr::Decimal(v)) => Some(RunningSum::Decimal(*v)),
+                    _ => Accumulator::numeric(&self.op, attr_name, record)?.map(RunningSum::Float),
+                }
+            }
+            FoldOperation::RMS(attr_name) => {
+                Accumulator::numeric(&self.op, attr_name, record)?.map(|v| RunningSum::Float(v * v))
+            }
+            _ => unreachable!(),
+        };
+        let (key, sum, count) = self
+            .groups
+            .entry(record.group_id)
+            .or_insert_with(|| (record.group_key.clone(), RunningSum::Float(0.0), 0));
+        if *key != record.group_key {
+            return Err(GroupCollision {
+                id: record.group_id,
+                key: key.clone(),
+                other_key: record.group_key.clone(),
+            }
+            .into());
+        }
+        if let Some(v) = value {
+            *sum = match (*sum, v) {
+                (RunningSum::Float(sum), RunningSum::Float(v)) => RunningSum::Float(sum + v),
+                // Like `Accumulator::DecimalSum`, a group whose first value is a decimal is summed
+                // exactly and only takes decimals, other groups take decimals as f64
+                #[cfg(feature = "decimal")]
+                (RunningSum::Float(_), RunningSum::Decimal(v)) if *count == 0 => {
+                    RunningSum::Decimal(v)
+                }
+                #[cfg(feature = "decimal")]
+                (RunningSum::Float(sum), RunningSum::Decimal(v)) => {
+                    RunningSum::Float(sum + Attr::Decimal(v).as_f64().unwrap_or(f64::NAN))
+                }
+                #[cfg(feature = "decimal")]
+                (RunningSum::Decimal(sum), RunningSum::Decimal(v)) => RunningSum::Decimal(sum + v),
+                #[cfg(feature = "decimal")]
+                (RunningSum::Decimal(_), RunningSum::Float(_)) => {
+                    return Err(FoldError {
+                        op: self.op.name(),
+                        attr_name: self.op.attr_name().unwrap_or_default().into(),
+                    }
+                    .into())
+                }
+            };
+            *count += 1;
+        }
+        Ok(())
+    }
+
+    /// Fold result of every group seen, numeric folds of a group without any value are `Null`
+    pub fn finish(self) -> HashMap<GroupKey, Attr> {
+        let op = self.op;
+        self.groups
+            .into_values()
+            .map(|(key, sum, count)| {
+                let attr = match (sum, &op) {
+                    (RunningSum::Float(sum), FoldOperation::COUNT | FoldOperation::COUNT_IF(_)) => {
+                        Attr::Int(sum as i32)
+                    }
+                    _ if count == 0 => Attr::Null,
+                    (RunningSum::Float(sum), FoldOperation::SUM(_)) => Attr::Float(sum as f32),
+                    (RunningSum::Float(sum), FoldOperation::AVG(_)) => {
+                        Attr::Float((sum / count as f64) as f32)
+                    }
+                    (RunningSum::Float(sum), _) => Attr::Float((sum / count as f64).sqrt() as f32),
+                    #[cfg(feature = "decimal")]
+                    (RunningSum::Decimal(sum), FoldOperation::SUM(_)) => Attr::Decimal(sum),
+                    #[cfg(feature = "decimal")]
+                    (RunningSum::Decimal(sum), _) => Attr::Decimal(sum / Decimal::from(count)),
+                };
+                (key, attr)
+            })
+            .collect()
+    }
+}
+
+/// Running state of a fold operation over the records of a group
+enum Accumulator<'a> {
+    Sum(Option<f64>),
+    Avg(f64, i32),
+    Count(i32),
+    CountDistinct(HashSet<&'a Attr>),
+    /// Frequency of each value
+    Tally(HashMap<&'a Attr, i32>),
+    /// Spread folds are computed in two passes, so all values are kept in f64
+    Values(Vec<f64>),
+    /// Record picked by `FIRST` or `LAST` so far
+    Pick(Option<&'a Record>),
+    Pieces(Vec<String>),
+    /// Sum and count of decimal attributes, which are kept exact. `Sum` and `Avg` turn into it
+    /// at the first decimal
+    #[cfg(feature = "decimal")]
+    DecimalSum(Decimal, i32),
+}
+
+impl<'a> Accumulator<'a> {
+    fn new(op: &FoldOperation) -> Self {
+        match op {
+            FoldOperation::SUM(_) => Accumulator::Sum(None),
+            FoldOperation::AVG(_) | FoldOperation::RMS(_) => Accumulator::Avg(0f64, 0),
+            FoldOperation::COUNT | FoldOperation::COUNT_IF(_) => Accumulator::Count(0),
+            FoldOperation::COUNT_DISTINCT(_) => Accumulator::CountDistinct(HashSet::new()),
+            FoldOperation::MODE(_) => Accumulator::Tally(HashMap::new()),
+            FoldOperation::VARIANCE(..)
+            | FoldOperation::STDDEV(..)
+            | FoldOperation::PERCENTILE { .. } => Accumulator::Values(Vec::new()),
+            FoldOperation::FIRST { .. } | FoldOperation::LAST { .. } => Accumulator::Pick(None),
+            FoldOperation::CONCAT { .. } => Accumulator::Pieces(Vec::new()),
+        }
+    }
+
+    /// Missing and `Null` attributes are skipped, other non-numeric ones fail numeric folds
+    fn numeric(
+        op: &FoldOperation,
+        attr_name: &str,
+        record: &Record,
+    ) -> Result<Option<f64>, FoldError> {
+        match record.attrs.get(attr_name) {
+            None | Some(Attr::Null) => Ok(None),
+            Some(attr) => attr.as_f64().map(Some).ok_or_else(|| FoldError {
+                op: op.name(),
+                attr_name: attr_name.into(),
+            }),
+        }
+    }
+
+    fn push(&mut self, op: &FoldOperation, record: &'a Record) -> Result<(), FoldError> {
+        #[cfg(feature = "decimal")]
+        if let FoldOperation::SUM(attr_name) | FoldOperation::AVG(attr_name) = op {
+            let fresh = matches!(self, Accumulator::Sum(None) | Accumulator::Avg(_, 0));
+            if fresh && matches!(record.attrs.get(attr_name), Some(Attr::Decimal(_))) {
+                *self = Accumulator::DecimalSum(Decimal::ZERO, 0);
+            }
+        }
+        match (self, op) {
+            (Accumulator::Sum(sum), FoldOperation::SUM(attr_name)) => {
+                if let Some(v) = Self::numeric(op, attr_name, record)? {
+                    *sum = Some(sum.unwrap_or(0f64) + v);
+                }
+            }
+            // mean of squares, rooted at the end
+            (Accumulator::Avg(sum, count), FoldOperation::RMS(attr_name)) => {
+                if let Some(v) = Self::numeric(op, attr_name, record)? {
+                    *sum += v * v;
+                    *count += 1;
+                }
+            }
+            (Accumulator::Avg(sum, count), FoldOperation::AVG(attr_name)) => {
+                if let Some(v) = Self::numeric(op, attr_name, record)? {
+                    *sum += v;
+                    *count += 1;
+                }
+            }
+            (Accumulator::Count(count), FoldOperation::COUNT_IF(predicate)) => {
+                if predicate.matches(record) {
+                    *count += 1
+                }
+            }
+            (Accumulator::Count(count), _) => *count += 1,
+            (Accumulator::CountDistinct(distinct), FoldOperation::COUNT_DISTINCT(attr_name)) => {
+                match record.attrs.get(attr_name) {
+                    None | Some(Attr::Null) => (),
+                    Some(attr) => {
+                        distinct.insert(attr);
+                    }
+                }
+            }
+            (Accumulator::Tally(tally), FoldOperation::MODE(attr_name)) => {
+                match record.attrs.get(attr_name) {
+                    None | Some(Attr::Null) => (),
+                    Some(attr) => *tally.entry(attr).or_insert(0) += 1,
+                }
+            }
+            (
+                Accumulator::Values(values),
+                FoldOperation::VARIANCE(attr_name, _)
+                | FoldOperation::STDDEV(attr_name, _)
+                | FoldOperation::PERCENTILE { attr_name, .. },
+            ) => {
+                if let Some(v) = Self::numeric(op, attr_name, record)? {
+                    values.push(v);
+                }
+            }
+            (Accumulator::Pick(picked), FoldOperation::FIRST { order_by, .. }) => {
+                Self::pick(picked, record, order_by, Ordering::Less)
+            }
+            (Accumulator::Pick(picked), FoldOperation::LAST { order_by, .. }) => {
+                Self::pick(picked, record, order_by, Ordering::Greater)
+            }
+            (Accumulator::Pieces(pieces), FoldOperation::CONCAT { attr_name, .. }) => {
+                match record.attrs.get(attr_name) {
+                    None | Some(Attr::Null) => (),
+                    Some(attr) => pieces.push(attr.to_string()),
+                }
+            }
+            #[cfg(feature = "decimal")]
+            (
+                Accumulator::DecimalSum(sum, count),
+                FoldOperation::SUM(attr_name) | FoldOperation::AVG(attr_name),
+            ) => match record.attrs.get(attr_name) {
+                None | Some(Attr::Null) => (),
+                Some(Attr::Decimal(v)) => {
+                    *sum += *v;
+                    *count += 1;
+                }
+                Some(_) => {
+                    return Err(FoldError {
+                        op: op.name(),
+                        attr_name: attr_name.into(),
+                    })
+                }
+            },
+            _ => panic!("Impossible: accumulator doesn't match fold operation"),
+        }
+        Ok(())
+    }
+
+    fn finish(self, op: &FoldOperation) -> Attr {
+        match (self, op) {
+            (Accumulator::Sum(sum), _) => sum.map_or(Attr::Null, |v| Attr::Float(v as f32)),
+            (Accumulator::Avg(_, 0), _) => Attr::Null,
+            (Accumulator::Avg(sum, count), FoldOperation::RMS(_)) => {
+                Attr::Float((sum / count as f64).sqrt() as f32)
+            }
+            (Accumulator::Avg(sum, count), _) => Attr::Float((sum / count as f64) as f32),
+            (Accumulator::Count(count), _) => Attr::Int(count),
+            (Accumulator::CountDistinct(distinct), _) => Attr::Int(distinct.len() as i32),
+            (Accumulator::Tally(tally), _) => tally
+                .into_iter()
+                .max_by(|(x, m), (y, n)| m.cmp(n).then_with(|| y.cmp(x)))
+                .map_or(Attr::Null, |(attr, _)| attr.clone()),
+            (Accumulator::Values(values), FoldOperation::VARIANCE(_, estimator)) => {
+                variance(&values, *estimator).map_or(Attr::Null, |v| Attr::Float(v as f32))
+            }
+            (Accumulator::Values(values), FoldOperation::STDDEV(_, estimator)) => {
+                variance(&values, *estimator).map_or(Attr::Null, |v| Attr::Float(v.sqrt() as f32))
+            }
+            (Accumulator::Values(values), FoldOperation::PERCENTILE { p, .. }) => {
+                percentile(values, *p).map_or(Attr::Null, |v| Attr::Float(v as f32))
+            }
+            (
+                Accumulator::Pick(picked),
+                FoldOperation::FIRST { value, .. } | FoldOperation::LAST { value, .. },
+            ) => picked
+                .and_then(|record| record.attrs.get(value))
+                .map_or(Attr::Null, |attr| attr.clone()),
+            (Accumulator::Pieces(pieces), _) if pieces.is_empty() => Attr::Null,
+            (Accumulator::Pieces(mut pieces), FoldOperation::CONCAT { sep, .. }) => {
+                pieces.sort();
+                Attr::Str(pieces.join(sep))
+            }
+            #[cfg(feature = "decimal")]
+            (Accumulator::DecimalSum(sum, _), FoldOperation::SUM(_)) => Attr::Decimal(sum),
+            #[cfg(feature = "decimal")]
+            (Accumulator::DecimalSum(sum, count), _) => Attr::Decimal(sum / Decimal::from(count)),
+            _ => panic!("Impossible: accumulator doesn't match fold operation"),
+        }
+    }
+
+    /// Replace the picked record if the record's `order_by` compares to it as `ord`
+    fn pick(picked: &mut Option<&'a Record>, record: &'a Record, order_by: &str, ord: Ordering) {
+        match record.attrs.get(order_by) {
+            None | Some(Attr::Null) => (),
+            Some(_) => match picked {
+                Some(current) if record.cmp_by(current, order_by, true) != ord => (),
+                _ => *picked = Some(record),
+            },
+        }
+    }
+}
+
+/// Two-pass variance. Values which are too few for the estimator, that is no value at all, or
+/// only one value with `Estimator::Sample`, have no variance
+fn variance(values: &[f64], estimator: Estimator) -> Option<f64> {
+    let n = values.len();
+    let divisor = match estimator {
+        Estimator::Population => n,
+        Estimator::Sample => n.saturating_sub(1),
+    };
+    if divisor == 0 {
+        return None;
+    }
+    let mean = values.iter().sum::<f64>() / n as f64;
+    let squared_deviation: f64 = values.iter().map(|v| (v - mean) * (v - mean)).sum();
+    Some(squared_deviation / divisor as f64)
+}
+
+/// Quantile with linear interpolation, at the rank `(n - 1) * p` of the sorted values
+fn percentile(mut values: Vec<f64>, p: f64) -> Option<f64> {
+    values.sort_by(f64::total_cmp);
+    sorted_percentile(&values, p)
+}
+
+/// `percentile` of values which are sorted already
+fn sorted_percentile(values: &[f64], p: f64) -> Option<f64> {
+    if values.is_empty() || !(0.0..=1.0).contains(&p) {
+        return None;
+    }
+    let rank = (values.len() - 1) as f64 * p;
+    let (lo, hi) = (rank.floor() as usize, rank.ceil() as usize);
+    Some(values[lo] + (rank - lo as f64) * (values[hi] - values[lo]))
+}
+
+/// Missing and `Null` attributes are skipped by numeric folds, a group without any value folds
+/// into `Null`
+///
+/// Numeric folds accumulate in f64, so sums of integers are exact up to 2^53, and results are
+/// rounded to `Attr::Float` once at the end. `SUM` and `AVG` of decimals are exact `Decimal`s,
+/// other numeric folds treat them as f64
+#[allow(non_camel_case_types)]
+pub enum FoldOperation {
+    AVG(String), // AVG of attr
+    SUM(String), // SUM of attr
+    COUNT,       // items count, records with `Null` attributes are counted as well
+    /// Count of the records which the predicate matches, like `COUNT(CASE WHEN ...)`
+    COUNT_IF(Predicate),
+    COUNT_DISTINCT(String), // distinct values count of attr, `Null` is not counted
+    /// The most frequent value of attr, the smallest one of equally frequent values. `Null` is
+    /// not counted
+    MODE(String),
+    VARIANCE(String, Estimator),
+    STDDEV(String, Estimator),
+    /// Root mean square of attr, `sqrt(mean(v * v))`
+    RMS(String),
+    /// `value` of the record with the smallest `order_by`, records whose `order_by` is missing or
+    /// `Null` are skipped, ties are broken like `Collection::sorted_records`
+    FIRST {
+        value: String,
+        order_by: String,
+    },
+    /// `value` of the record with the largest `order_by`, see `FIRST`
+    LAST {
+        value: String,
+        order_by: String,
+    },
+    /// Displayed attributes joined with `sep`, they are sorted first so the result is
+    /// reproducible. Missing and `Null` attributes are skipped
+    CONCAT {
+        attr_name: String,
+        sep: String,
+    },
+    /// The `p`-th quantile of attr, `p` in `0.0..=1.0`. It's linearly interpolated between the
+    /// two closest ranks of the sorted values, so `0.0` is the minimum and `1.0` the maximum. Any
+    /// other `p` folds into `Null`
+    PERCENTILE {
+        attr_name: String,
+        p: f64,
+    },
+}
+
+impl FoldOperation {
+    fn name(&self) -> &'static str {
+        match self {
+            FoldOperation::AVG(_) => "AVG",
+            FoldOperation::SUM(_) => "SUM",
+            FoldOperation::COUNT => "COUNT",
+            FoldOperation::COUNT_IF(_) => "COUNT_IF",
+            FoldOperation::COUNT_DISTINCT(_) => "COUNT_DISTINCT",
+            FoldOperation::MODE(_) => "MODE",
+            FoldOperation::VARIANCE(..) => "VARIANCE",
+            FoldOperation::STDDEV(..) => "STDDEV",
+            FoldOperation::RMS(_) => "RMS",
+            FoldOperation::FIRST { .. } => "FIRST",
+            FoldOperation::LAST { .. } => "LAST",
+            FoldOperation::CONCAT { .. } => "CONCAT",
+            FoldOperation::PERCENTILE { .. } => "PERCENTILE",
+        }
+    }
+
+    /// The folded attribute, `COUNT` and `COUNT_IF` fold none
+    fn attr_name(&self) -> Option<&str> {
+        match self {
+            FoldOperation::AVG(attr_name)
+            | FoldOperation::SUM(attr_name)
+            | FoldOperation::COUNT_DISTINCT(attr_name)
+            | FoldOperation::MODE(attr_name)
+            | FoldOperation::VARIANCE(attr_name, _)
+            | FoldOperation::STDDEV(attr_name, _)
+            | FoldOperation::RMS(attr_name)
+            | FoldOperation::FIRST {
+                value: attr_name, ..
+            }
+            | FoldOperation::LAST {
+                value: attr_name, ..
+            }
+            | FoldOperation::CONCAT { attr_name, .. }
+            | FoldOperation::PERCENTILE { attr_name, .. } => Some(attr_name),
+            FoldOperation::COUNT | FoldOperation::COUNT_IF(_) => None,
+        }
+    }
+}
+
+/// Displayed like `AVG(i)`, it's the header of the fold result column
+impl fmt::Display for FoldOperation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FoldOperation::AVG(attr_name)
+            | FoldOperation::SUM(attr_name)
+            | FoldOperation::COUNT_DISTINCT(attr_name)
+            | FoldOperation::MODE(attr_name)
+            | FoldOperation::RMS(attr_name)
+            | FoldOperation::CONCAT { attr_name, .. } => {
+                write!(f, "{}({})", self.name(), attr_name)
+            }
+            FoldOperation::COUNT => write!(f, "{}", self.name()),
+            FoldOperation::COUNT_IF(predicate) => write!(f, "{}({})", self.name(), predicate),
+            FoldOperation::VARIANCE(attr_name, estimator)
+            | FoldOperation::STDDEV(attr_name, estimator) => {
+                let suffix = match estimator {
+                    Estimator::Population => "POP",
+                    Estimator::Sample => "SAMP",
+                };
+                write!(f, "{}_{}({})", self.name(), suffix, attr_name)
+            }
+            FoldOperation::FIRST { value, order_by } | FoldOperation::LAST { value, order_by } => {
+                write!(f, "{}({} ORDER BY {})", self.name(), value, order_by)
+            }
+            FoldOperation::PERCENTILE { attr_name, p } => {
+                write!(f, "{}({}, {})", self.name(), attr_name, p)
+            }
+        }
+    }
+}
+
+/// Table produced by `Collection::pivot`, rows and columns are ordered by their values
+#[derive(PartialEq, Debug)]
+pub struct PivotTable {
+    index: Vec<Attr>,
+    columns: Vec<Attr>,
+    /// A row per index value, each has a cell per column value
+    cells: Vec<Vec<Attr>>,
+}
+
+impl PivotTable {
+    /// Distinct values of the index attribute, a row for each
+    pub fn index(&self) -> &[Attr] {
+        &self.index
+    }
+
+    /// Distinct values of the columns attribute, a column for each
+    pub fn columns(&self) -> &[Attr] {
+        &self.columns
+    }
+
+    /// Cell at the row of `index` and the column of `column`, `None` if either is unknown
+    pub fn get(&self, index: &Attr, column: &Attr) -> Option<&Attr> {
+        let row = self.index.binary_search(index).ok()?;
+        let column = self.columns.binary_search(column).ok()?;
+        Some(&self.cells[row][column])
+    }
+
+    /// Rows in order, each is the index value and its cells in the order of `columns`
+    pub fn rows(&self) -> impl Iterator<Item = (&Attr, &[Attr])> {
+        zip(&self.index, &self.cells).map(|(index, cells)| (index, cells.as_slice()))
+    }
+}
+
+/// Error emitted when a numeric fold meets a non-numeric attribute
+#[derive(PartialEq, Debug)]
+pub struct FoldError {
+    /// Name of the fold operation, like `AVG` or `SUM`
+    pub op: &'static str,
+    pub attr_name: String,
+}
+
+impl fmt::Display for FoldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} operation should be performed on int or float",
+            self.op
+        )
+    }
+}
+
+impl error::Error for FoldError {}
+
+/// Summary statistics of a numeric attribute in a group, see `Collection::describe`. Only
+/// `count` is known for a group without any value, `stddev` needs two values
+#[derive(Clone, PartialEq, Debug)]
+pub struct Describe {
+    pub count: usize,
+    pub mean: Option<f64>,
+    /// Sample standard deviation, divided by n - 1
+    pub stddev: Option<f64>,
+    pub min: Option<f64>,
+    pub p25: Option<f64>,
+    pub p50: Option<f64>,
+    pub p75: Option<f64>,
+    pub max: Option<f64>,
+}
+
+impl Describe {
+    fn new(values: &[f64]) -> Self {
+        let quantile = |p| sorted_percentile(values, p);
+        Describe {
+            count: values.len(),
+            mean: (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64),
+            stddev: variance(values, Estimator::Sample).map(f64::sqrt),
+            min: values.first().copied(),
+            p25: quantile(0.25),
+            p50: quantile(0.5),
+            p75: quantile(0.75),
+            max: values.last().copied(),
+        }
+    }
+}
+
+/// Divisor used by spread folds like `VARIANCE` and `STDDEV`
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Estimator {
+    Population, // divided by n
+    Sample,     // divided by n - 1
+}
+
+/// FoldResult is binding to collection and fold_func, and mapping each group to a scalar result
+pub struct FoldResult<'a> {
+    collection: ByAddress<&'a Collection<'a>>,
+    fold_func: FoldOperation,
+    result: HashMap<ByAddress<&'a Group<'a>>, Attr>,
+}
+
+impl<'a> FoldResult<'a> {
+    /// Groups and their results in arbitrary order
+    pub fn iter(&self) -> impl Iterator<Item = (&Group<'a>, &Attr)> {
+        self.result.iter().map(|(group, attr)| (**group, attr))
+    }
+
+    pub fn get(&self, group_id: u64) -> Option<&Attr> {
+        self.collection
+            .groups
+            .get(&group_id)
+            .and_then(|group| self.result.get(&ByAddress(group)))
+    }
+
+    pub fn operation(&self) -> &FoldOperation {
+        &self.fold_func
+    }
+
+    /// Key of each group paired with its result, so the result tells which group each value
+    /// belongs to, like `[("userid", Int(0))]`. Ordered by group id
+    pub fn labeled(&self) -> Vec<(Vec<(String, Attr)>, Attr)> {
+        let mut rows: Vec<(&Group, &Attr)> = self
+            .result
+            .iter()
+            .map(|(group, attr)| (**group, attr))
+            .collect();
+        rows.sort_by_key(|(group, _)| group.id);
+        rows.into_iter()
+            .map(|(group, attr)| (group.key.clone(), attr.clone()))
+            .collect()
+    }
+
+    /// Write the result as csv with a column per group-by attribute holding the group's key and
+    /// a column named after the fold operation, like `AVG(i)`. Rows are ordered by group id
+    pub fn write_csv<W: io::Write>(&self, wtr: W) -> Result<(), csv::Error> {
+        self.write_csv_with_precision(wtr, None)
+    }
+
+    /// `write_csv` with float results rounded to `precision` decimals, when it's given
+    pub fn write_csv_with_precision<W: io::Write>(
+        &self,
+        wtr: W,
+        precision: Option<usize>,
+    ) -> Result<(), csv::Error> {
+        self.write_rows(wtr, self.labeled(), precision)
+    }
+
+    /// `write_csv_with_precision` with rows ordered by group key instead, like
+    /// `Collection::groups_sorted_by_key`, so the order doesn't depend on group ids
+    pub fn write_csv_sorted_by_key<W: io::Write>(
+        &self,
+        wtr: W,
+        precision: Option<usize>,
+    ) -> Result<(), csv::Error> {
+        let mut rows = self.labeled();
+        rows.sort_by(|(x, _), (y, _)| x.cmp(y));
+        self.write_rows(wtr, rows, precision)
+    }
+
+    fn write_rows<W: io::Write>(
+        &self,
+        wtr: W,
+        rows: Vec<(Vec<(String, Attr)>, Attr)>,
+        precision: Option<usize>,
+    ) -> Result<(), csv::Error> {
+        let mut headers: Vec<String> = rows.first().map_or_else(Vec::new, |(key, _)| {
+            key.iter().map(|(name, _)| name.to_owned()).collect()
+        });
+        headers.push(self.fold_func.to_string());
+        let mut wtr = csv::Writer::from_writer(wtr);
+        wtr.write_record(&headers)?;
+        for (key, attr) in rows {
+            let mut row: Vec<String> = key
+                .iter()
+                .map(|(_, key)| key.to_string_with_precision(precision))
+                .collect();
+            row.push(attr.to_string_with_precision(precision));
+            wtr.write_record(&row)?;
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+
+    /// The result as a json array of objects, each has the group-by attributes of a group and
+    /// its result as `value`, like `{"userid": 0, "value": 3}`. Ordered by group id
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> serde_json::Value {
+        self.labeled()
+            .into_iter()
+            .map(|(key, attr)| {
+                let mut object: serde_json::Map<_, _> = key
+                    .into_iter()
+                    .map(|(name, attr)| (name, attr.to_json()))
+                    .collect();
+                object.insert("value".into(), attr.to_json());
+                object
+            })
+            .collect()
+    }
+
+    /// Render the result as a GitHub-flavored Markdown table, with a column per group-by
+    /// attribute holding the group's key and a column named after the fold operation. Rows are
+    /// ordered by group id, `|` in cells is escaped
+    pub fn to_markdown(&self) -> String {
+        let rows = self.labeled();
+        let mut headers: Vec<String> = rows.first().map_or_else(Vec::new, |(key, _)| {
+            key.iter().map(|(name, _)| name.to_owned()).collect()
+        });
+        headers.push(self.fold_func.to_string());
+
+        let line = |cells: Vec<String>| -> String {
+            let cells: Vec<String> = cells.iter().map(|cell| cell.replace('|', "\\|")).collect();
+            format!("| {} |\n", cells.join(" | "))
+        };
+        let mut table = line(headers.clone());
+        table += &format!("|{}|\n", vec![" --- "; headers.len()].join("|"));
+        for (key, attr) in rows {
+            let mut cells: Vec<String> = key.iter().map(|(_, key)| key.to_string()).collect();
+            cells.push(attr.to_string());
+            table += &line(cells);
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_a_ctx() -> Ctx {
+        let mut ctx = Ctx::new();
+        ctx.add_attr_type("userid", Attr::Int(0), Some(ComponentRule::Unique));
+        ctx.add_attr_type(
+            "time",
+            Attr::Float(0f32),
+            Some(ComponentRule::Interval(Interval { start: 1, step: 3 })),
+        );
+        ctx.add_attr_type("i", Attr::Int(0), None);
+        ctx.add_attr_type("f", Attr::Float(0f32), None);
+        ctx.add_attr_type("b", Attr::Bool(false), None);
+        ctx.add_attr_type("s", Attr::Str("hello".into()), None);
+        ctx
+    }
+
+    #[test]
+    fn construct_record() {
+        let headers = ["userid", "time", "i", "f", "b", "s"];
+        let raw_record_a = vec!["23", "2", "0", ".23", "true", "hello"];
+        let raw_record_b = ["23", "3", "8", ".45", "true", "world"];
+        let raw_record_c = ["24", "2", "1", ".25", "false", "yeah"];
+        let ctx = make_a_ctx();
+
+        let record_a = Record::new(
+            &ctx,
+            zip(headers.iter(), raw_record_a)
+                .map(|(x, y)| (x.to_owned(), y))
+                .collect(),
+        )
+        .unwrap();
+
+        assert_eq!(record_a.attrs.get("userid"), Some(&Attr::Int(23)));
+        assert_eq!(record_a.attrs.get("time"), Some(&Attr::Float(2f32)));
+        assert_eq!(record_a.attrs.get("i"), Some(&Attr::Int(0)));
+        assert_eq!(record_a.attrs.get("f"), Some(&Attr::Float(0.23f32)));
+        assert_eq!(record_a.attrs.get("b"), Some(&Attr::Bool(true)));
+        assert_eq!(record_a.attrs.get("x"), None);
+
+        let record_b = Record::new(
+            &ctx,
+            zip(headers.iter(), raw_record_b.iter())
+                .map(|(x, y)| (x.to_owned(), y.to_owned()))
+                .collect(),
+        )
+        .unwrap();
+        let record_c = Record::new(
+            &ctx,
+            zip(headers.iter(), raw_record_c.iter())
+                .map(|(x, y)| (x.to_owned(), y.to_owned()))
+                .collect(),
+        )
+        .unwrap();
+
+        assert_eq!(record_a.group_id, record_b.group_id);
+        assert_ne!(record_a.group_id, record_c.group_id);
+    }
+
+    #[test]
+    fn group_record() {
+        let headers = ["userid", "time"];
+        let ctx = make_a_ctx();
+        let record_group_a = vec![
+            vec!["0", "1.1"],
+            vec!["0", "2.9"],
+            vec!["0", "3"],
+            vec!["0", "3.9"],
+        ];
+        let record_group_b = vec![vec!["0", "7.9"], vec!["0", "9"]];
+        let record_group_c = vec![vec!["1", "4.1"], vec!["1", "6.9"]];
+
+        let get_group_state = |record_group: Vec<Vec<&str>>| -> (bool, Option<u64>) {
+            record_group
+                .into_iter()
+                .map(|raw_record| {
+                    Record::new(
+                        &ctx,
+                        zip(headers.iter(), raw_record.iter())
+                            .map(|(x, y)| (x.to_owned(), y.to_owned()))
+                            .collect(),
+                    )
+                    .unwrap()
+                })
+                .fold((true, None), |(is_same_group_id, group_id), x| {
+                    if let Some(group_id) = group_id {
+                        if is_same_group_id && x.group_id == group_id {
+                            (true, Some(x.group_id))
+                        } else {
+                            (false, Some(x.group_id))
+                        }
+                    } else {
+                        (true, Some(x.group_id))
+                    }
+                })
+        };
+
+        let (is_same_group_id_a, group_id_a) = get_group_state(record_group_a);
+        let (is_same_group_id_b, group_id_b) = get_group_state(record_group_b);
+        let (is_same_group_id_c, group_id_c) = get_group_state(record_group_c);
+        assert!(is_same_group_id_a);
+        assert!(is_same_group_id_b);
+        assert!(is_same_group_id_c);
+        assert_ne!(group_id_a, group_id_b);
+        assert_ne!(group_id_b, group_id_c);
+        assert_ne!(group_id_c, group_id_a);
+    }
+
+    #[test]
+    fn construct_collection() {
+        let headers = ["userid", "time"];
+        let ctx = make_a_ctx();
+        let records = vec![
+            // group_a
+            vec!["0", "1.1"],
+            vec!["0", "2.9"],
+            vec!["0", "3"],
+            vec!["0", "3.9"],
+            // group_b
+            vec!["0", "7.9"],
+            vec!["0", "9"],
+            // group_c
+            vec!["1", "4.1"],
+            vec!["1", "6.9"],
+        ];
+        let records: Vec<_> = records
+            .into_iter()
+            .map(|raw_record| {
+                Record::new(
+                    &ctx,
+                    zip(headers.iter(), raw_record)
+                        .map(|(x, y)| (x.to_owned(), y))
+                        .collect(),
+                )
+                .unwrap()
+            })
+            .collect();
+        let collection = Collection::new(records.iter().collect());
+        assert_eq!(collection.groups.len(), 3);
+    }
+
+    #[test]
+    fn count_groups_and_records() {
+        let headers = ["userid", "time"];
+        let ctx = make_a_ctx();
+        let records: Vec<_> = [
+            vec!["0", "1.1"],
+            vec!["0", "2.9"],
+            vec!["0", "3"],
+            vec!["0", "3.9"],
+            vec!["0", "7.9"],
+            vec!["0", "9"],
+            vec!["1", "4.1"],
+            vec!["1", "6.9"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let collection = Collection::new(records.iter().collect());
+        assert_eq!(collection.group_count(), 3);
+        assert_eq!(collection.record_count(), 8);
+        assert!(!collection.is_empty());
+        assert!(Collection::new(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn retain_groups() {
+        let headers = ["userid", "time"];
+        let ctx = make_a_ctx();
+        let records: Vec<_> = [
+            vec!["0", "1.1"],
+            vec!["0", "2.9"],
+            vec!["0", "3"],
+            vec!["0", "3.9"],
+            vec!["0", "7.9"],
+            vec!["0", "9"],
+            vec!["1", "4.1"],
+            vec!["1", "6.9"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let collection = Collection::new(records.iter().collect());
+        let big_groups = collection
+            .clone()
+            .retain_groups(|group| group.records().count() >= 3);
+        assert_eq!(big_groups.group_count(), 1);
+        assert_eq!(big_groups.record_count(), 4);
+        let user_groups = collection.retain_groups(|group| {
+            group
+                .key()
+                .iter()
+                .any(|(name, key)| name == "userid" && *key == Attr::Int(0))
+        });
+        assert_eq!(user_groups.group_count(), 2);
+    }
+
+    #[test]
+    fn size_by_group() {
+        let headers = ["userid", "time"];
+        let ctx = make_a_ctx();
+        let records: Vec<_> = [
+            vec!["0", "1.1"],
+            vec!["0", "2.9"],
+            vec!["0", "3"],
+            vec!["0", "3.9"],
+            vec!["0", "7.9"],
+            vec!["0", "9"],
+            vec!["1", "4.1"],
+            vec!["1", "6.9"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let collection = Collection::new(records.iter().collect());
+        let sizes = collection.size_by_group();
+        assert_eq!(sizes[&records[0].group_id()], 4);
+        assert_eq!(sizes[&records[4].group_id()], 2);
+        assert_eq!(sizes[&records[6].group_id()], 2);
+        assert_eq!(sizes.values().sum::<usize>(), collection.record_count());
+    }
+
+    #[test]
+    fn filter_collection() {
+        let headers = ["userid", "time", "i"];
+        let ctx = make_a_ctx();
+        let records = vec![
+            vec!["0", "1", "233"],
+            vec!["0", "1", "23"],
+            vec!["0", "1", "2333"],
+            vec!["0", "1", "0"],
+            vec!["0", "1", "-28"],
+            vec!["0", "1", "233"],
+            vec!["0", "1", "366"],
+            vec!["0", "1", "243"],
+        ];
+        let records: Vec<_> = records
+            .into_iter()
+            .map(|raw_record| {
+                Record::new(
+                    &ctx,
+                    zip(headers.iter(), raw_record)
+                        .map(|(x, y)| (x.to_owned(), y))
+                        .collect(),
+                )
+                .unwrap()
+            })
+            .collect();
+        let collection = Collection::new(records.iter().collect());
+        let filter_cond = FilterCond {
+            attr_name: "i".into(),
+            val: Attr::Int(232),
+            op: CmpOp::Gt,
+        };
+        let collection = collection.filter_records(filter_cond);
+
+        assert_eq!(collection.groups.iter().next().unwrap().1.records.len(), 5);
+    }
+
+    #[test]
+    fn split_collection_by() {
+        let headers = ["userid", "time", "i"];
+        let ctx = make_a_ctx();
+        let records: Vec<_> = ["233", "23", "2333", "0", "-28", "233", "366", "243"]
+            .into_iter()
+            .map(|i| Record::new(&ctx, zip(headers, ["0", "1", i]).collect()).unwrap())
+            .collect();
+        let collection = Collection::new(records.iter().collect());
+        let greater = |record: &Record| record.attrs["i"] > Attr::Int(232);
+        let (matched, unmatched) = collection.clone().split_by(greater);
+        assert_eq!(matched.record_count(), 5);
+        assert_eq!(unmatched.record_count(), 3);
+        assert!(matched.records().into_iter().all(greater));
+        assert!(!unmatched.records().into_iter().any(greater));
+        assert_eq!(
+            matched.clone().union(&unmatched).records().len(),
+            collection.record_count()
+        );
+        assert!(matched.clone().intersection(&unmatched).is_empty());
+        let (all, none) = collection.clone().split_by(|_| true);
+        assert_eq!(all.record_count(), 8);
+        assert!(none.is_empty());
+    }
+
+    #[test]
+    fn filter_between() {
+        let headers = ["userid", "time", "i"];
+        let ctx = make_a_ctx();
+        let records: Vec<_> = ["233", "23", "2333", "0", "-28", "233", "366", "243"]
+            .into_iter()
+            .map(|i| Record::new(&ctx, zip(headers, ["0", "1", i]).collect()).unwrap())
+            .collect();
+        let collection = Collection::new(records.iter().collect());
+        let between = |inclusive| Predicate::Between {
+            attr_name: "i".into(),
+            low: Attr::Int(233),
+            high: Attr::Int(366),
+            inclusive,
+        };
+        assert_eq!(between((true, false)).to_string(), "233 <= i < 366");
+        let in_range = |inclusive| {
+            let mut values: Vec<Attr> = collection
+                .clone()
+                .filter_records(between(inclusive))
+                .records()
+                .into_iter()
+                .map(|record| record.attrs["i"].clone())
+                .collect();
+            values.sort();
+            values
+        };
+        assert_eq!(in_range((true, true)), [233, 233, 243, 366].map(Attr::Int));
+        assert_eq!(in_range((true, false)), [233, 233, 243].map(Attr::Int));
+        assert_eq!(in_range((false, false)), [Attr::Int(243)]);
+
+        // an int column against float literals is compared by value
+        let count =
+            |predicate: Predicate| collection.clone().filter_records(predicate).record_count();
+        let cmp = |op, val| Predicate::Cmp {
+            attr_name: "i".into(),
+            val,
+            op,
+        };
+        assert_eq!(count(cmp(CmpOp::Gt, Attr::Float(232.5))), 5);
+        assert_eq!(count(cmp(CmpOp::Lt, Attr::Float(0.5))), 2);
+        assert_eq!(count(cmp(CmpOp::Eq, Attr::Float(233.0))), 2);
+        assert_eq!(count(cmp(CmpOp::Ge, Attr::Int64(243))), 3);
+        assert_eq!(count(cmp(CmpOp::Ne, Attr::Float(f32::NAN))), 0);
+        assert_eq!(
+            count(Predicate::Between {
+                attr_name: "i".into(),
+                low: Attr::Float(232.5),
+                high: Attr::Float(243.5),
+                inclusive: (false, false),
+            }),
+            3
+        );
+    }
+
+    #[test]
+    fn set_operations_on_collections() {
+        let headers = ["userid", "time", "i"];
+        let ctx = make_a_ctx();
+        let records = vec![
+            vec!["0", "1", "233"],
+            vec!["0", "1", "23"],
+            vec!["0", "1", "2333"],
+            vec!["0", "1", "0"],
+            vec!["0", "1", "-28"],
+            vec!["0", "1", "233"],
+            vec!["0", "1", "366"],
+            vec!["0", "1", "243"],
+        ];
+        let records: Vec<_> = records
+            .into_iter()
+            .map(|raw_record| {
+                Record::new(
+                    &ctx,
+                    zip(headers.iter(), raw_record)
+                        .map(|(x, y)| (x.to_owned(), y))
+                        .collect(),
+                )
+                .unwrap()
+            })
+            .collect();
+        let whole_view = records.iter().collect();
+        let end_with_3_view = records
+            .iter()
+            .filter(|record| {
+                if let Some(Attr::Int(x)) = record.attrs.get("i") {
+                    x % 10 == 3
+                } else {
+                    false
+                }
+            })
+            .collect();
+        let whole_view = Collection::new(whole_view);
+        let end_with_3_view = Collection::new(end_with_3_view);
+        let intersection = whole_view.intersection(&end_with_3_view);
+
+        assert_eq!(
+            intersection.groups.iter().next().unwrap().1.records.len(),
+            5
+        );
+
+        let union = intersection.union(&end_with_3_view);
+        assert_eq!(union.groups.iter().next().unwrap().1.records.len(), 5);
+
+        let difference = union.difference(&end_with_3_view);
+        assert_eq!(difference.groups.len(), 0);
+    }
+
+    #[test]
+    fn filter_with_closure() {
+        let headers = ["userid", "time", "i"];
+        let ctx = make_a_ctx();
+        let records: Vec<_> = [
+            vec!["0", "1", "233"],
+            vec!["0", "1", "23"],
+            vec!["0", "1", "2333"],
+            vec!["0", "1", "0"],
+            vec!["0", "1", "-28"],
+            vec!["0", "1", "233"],
+            vec!["0", "1", "366"],
+            vec!["0", "1", "243"],
+            vec!["1", "1", "7"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let ends_with_3 =
+            |record: &Record| matches!(record.attrs.get("i"), Some(Attr::Int(x)) if x % 10 == 3);
+        let end_with_3_view = Collection::new(records.iter().filter(|x| ends_with_3(x)).collect());
+
+        let filtered = Collection::new(records.iter().collect()).filter_with(ends_with_3);
+        assert_eq!(filtered.record_count(), 5);
+        // the group of userid 1 is dropped
+        assert_eq!(filtered.group_count(), 1);
+        assert!(filtered.is_subset(&end_with_3_view) && end_with_3_view.is_subset(&filtered));
+    }
+
+    #[test]
+    fn fold_collections() {
+        let headers = ["userid", "time", "i"];
+        let ctx = make_a_ctx();
+        let records = vec![
+            vec!["0", "1", "233"],
+            vec!["0", "1", "23"],
+            vec!["0", "1", "2333"],
+            vec!["0", "1", "0"],
+            vec!["0", "1", "-28"],
+            vec!["0", "1", "233"],
+            vec!["0", "1", "366"],
+            vec!["0", "1", "243"],
+        ];
+        let records: Vec<_> = records
+            .into_iter()
+            .map(|raw_record| {
+                Record::new(
+                    &ctx,
+                    zip(headers.iter(), raw_record)
+                        .map(|(x, y)| (x.to_owned(), y))
+                        .collect(),
+                )
+                .unwrap()
+            })
+            .collect();
+        let view = records.iter().collect();
+        let collection = Collection::new(view);
+
+        let count_result = collection.fold(FoldOperation::COUNT).unwrap();
+        assert_eq!(count_result.result.len(), 1);
+        assert_eq!(count_result.result.iter().next().unwrap().1, &Attr::Int(8));
+
+        let sum_result = collection.fold(FoldOperation::SUM("i".into())).unwrap();
+        assert_eq!(
+            sum_result.result.iter().next().unwrap().1,
+            &Attr::Float(3403f32)
+        );
+
+        let avg_result = collection.fold(FoldOperation::AVG("i".into())).unwrap();
+        assert_eq!(
+            avg_result.result.iter().next().unwrap().1,
+            &Attr::Float(425.375)
+        );
+    }
+
+    #[test]
+    fn unexpected_header() {
+        let ctx = make_a_ctx();
+        let headers = ["userid", "time", "x"];
+        let record = vec!["0", "0", "0"];
+        let err = Record::new(&ctx, zip(headers, record).collect())
+            .err()
+            .unwrap();
+        assert!(matches!(&err, Error::MissingHeader(header) if header == "x"));
+        assert_eq!(
+            err.to_string(),
+            "Error: header `x' is not found in context info"
+        );
+    }
+
+    #[test]
+    fn missing_group_key() {
+        let ctx = make_a_ctx();
+        let err = Record::new(&ctx, vec![("userid", "0"), ("i", "1")])
+            .err()
+            .unwrap();
+        assert!(matches!(&err, Error::MissingGroupKey(attr_name) if attr_name == "time"));
+        assert_eq!(err.to_string(), "Error: key attribute is not found");
+    }
+
+    #[test]
+    fn fold_on_non_numeric_attribute() {
+        let ctx = make_a_ctx();
+        let records =
+            [Record::new(&ctx, vec![("userid", "0"), ("time", "1"), ("s", "a")]).unwrap()];
+        let collection = Collection::new(records.iter().collect());
+        let err: Error = collection
+            .fold(FoldOperation::AVG("s".into()))
+            .err()
+            .unwrap()
+            .into();
+        assert!(matches!(&err, Error::Fold(e) if e.op == "AVG" && e.attr_name == "s"));
+        assert_eq!(
+            err.to_string(),
+            "AVG operation should be performed on int or float"
+        );
+        assert!(collection
+            .filter_groups_by_fold(FoldOperation::SUM("s".into()), CmpOp::Lt, Attr::Int(0))
+            .is_err());
+    }
+
+    #[test]
+    fn invalid_attr_type() {
+        let ctx = make_a_ctx();
+        let headers = ["userid", "time", "i"];
+        let record = vec!["0", "0", "true"];
+        let err = match Record::new(&ctx, zip(headers, record).collect()) {
+            Err(Error::Parse(err)) => err,
+            _ => panic!("expect a parse error"),
+        };
+        assert_eq!(
+            err,
+            ParseError {
+                header: "i".into(),
+                raw_attr: "true".into(),
+                expected: "int",
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "Error: expect int when parsing attribute `i', which value is `true'"
+        );
+    }
+
+    #[test]
+    fn int64_attr() {
+        let mut ctx = Ctx::new();
+        ctx.add_attr_type(
+            "ts",
+            Attr::Int64(0),
+            Some(ComponentRule::Interval(Interval {
+                start: 0,
+                step: 1000,
+            })),
+        );
+        let records: Vec<_> = ["1700000000000", "1700000000999", "1700000001000"]
+            .into_iter()
+            .map(|raw_attr| Record::new(&ctx, vec![("ts", raw_attr)]).unwrap())
+            .collect();
+
+        assert_eq!(
+            records[0].attrs.get("ts"),
+            Some(&Attr::Int64(1_700_000_000_000))
+        );
+        assert_eq!(records[0].group_id, records[1].group_id);
+        assert_ne!(records[1].group_id, records[2].group_id);
+
+        let collection = Collection::new(records.iter().collect());
+        assert_eq!(collection.groups.len(), 2);
+        let sum_result = collection.fold(FoldOperation::SUM("ts".into())).unwrap();
+        let total: f32 = sum_result
+            .result
+            .values()
+            .map(|sum| match sum {
+                Attr::Float(v) => *v,
+                _ => panic!("SUM should evaluate to float"),
+            })
+            .sum();
+        assert!((total - 5.1e12).abs() / 5.1e12 < 1e-6);
+    }
+
+    #[test]
+    fn null_attr() {
+        let mut ctx = make_a_ctx();
+        ctx.set_nullable("i");
+        let headers = ["userid", "time", "i"];
+        let records: Vec<_> = [
+            vec!["0", "1", "2"],
+            vec!["0", "1", ""],
+            vec!["0", "1", "NA"],
+            vec!["0", "1", "4"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        assert_eq!(records[1].attrs.get("i"), Some(&Attr::Null));
+        assert_eq!(records[2].attrs.get("i"), Some(&Attr::Null));
+
+        // Non-nullable attributes still reject empty cells
+        assert!(Record::new(&ctx, vec![("userid", "")]).is_err());
+
+        let collection = Collection::new(records.iter().collect());
+        let sum_result = collection.fold(FoldOperation::SUM("i".into())).unwrap();
+        assert_eq!(sum_result.result.values().next(), Some(&Attr::Float(6f32)));
+        let avg_result = collection.fold(FoldOperation::AVG("i".into())).unwrap();
+        assert_eq!(avg_result.result.values().next(), Some(&Attr::Float(3f32)));
+        let count_result = collection.fold(FoldOperation::COUNT).unwrap();
+        assert_eq!(count_result.result.values().next(), Some(&Attr::Int(4)));
+
+        let null_only = collection.filter_records(FilterCond {
+            attr_name: "i".into(),
+            val: Attr::Int(3),
+            op: CmpOp::Gt,
+        });
+        assert_eq!(null_only.groups.values().next().unwrap().records.len(), 1);
+    }
+
+    #[test]
+    fn custom_null_literals() {
+        let mut ctx = make_a_ctx();
+        ctx.set_nullable("i");
+        ctx.set_null_literals(&["-"]);
+        assert_eq!(Attr::new(&ctx, "i", "-").unwrap(), Attr::Null);
+        assert!(Attr::new(&ctx, "i", "NA").is_err());
+
+        let records =
+            [Record::new(&ctx, vec![("userid", "0"), ("time", "1"), ("i", "-")]).unwrap()];
+        let collection = Collection::new(records.iter().collect());
+        assert_eq!(
+            collection
+                .fold(FoldOperation::AVG("i".into()))
+                .unwrap()
+                .result
+                .values()
+                .next(),
+            Some(&Attr::Null)
+        );
+    }
+
+    #[test]
+    fn count_distinct() {
+        let headers = ["userid", "time", "i", "f"];
+        let mut ctx = make_a_ctx();
+        ctx.set_nullable("i");
+        let records: Vec<_> = [
+            vec!["0", "1", "233", "0.5"],
+            vec!["0", "1", "23", "0.5"],
+            vec!["0", "1", "233", "-0"],
+            vec!["0", "1", "", "0"],
+            vec!["0", "1", "23", "0.5"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let collection = Collection::new(records.iter().collect());
+
+        let result = collection
+            .fold(FoldOperation::COUNT_DISTINCT("i".into()))
+            .unwrap();
+        assert_eq!(result.result.values().next(), Some(&Attr::Int(2)));
+        let result = collection
+            .fold(FoldOperation::COUNT_DISTINCT("f".into()))
+            .unwrap();
+        assert_eq!(result.result.values().next(), Some(&Attr::Int(3)));
+    }
+
+    #[test]
+    fn spread_folds() {
+        let headers = ["userid", "time", "i"];
+        let ctx = make_a_ctx();
+        let records: Vec<_> = [
+            vec!["0", "1", "233"],
+            vec!["0", "1", "23"],
+            vec!["0", "1", "2333"],
+            vec!["0", "1", "0"],
+            vec!["0", "1", "-28"],
+            vec!["0", "1", "233"],
+            vec!["0", "1", "366"],
+            vec!["0", "1", "243"],
+            // a group with single record
+            vec!["1", "1", "7"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let collection = Collection::new(records.iter().collect());
+        let big_group = ByAddress(
+            collection
+                .groups
+                .values()
+                .find(|group| group.records.len() == 8)
+                .unwrap(),
+        );
+        let small_group = ByAddress(
+            collection
+                .groups
+                .values()
+                .find(|group| group.records.len() == 1)
+                .unwrap(),
+        );
+        let assert_near = |attr: &Attr, expected: f32| match attr {
+            Attr::Float(v) => assert!(
+                (v - expected).abs() <= expected.abs() * 1e-6 + 1e-4,
+                "{} != {}",
+                v,
+                expected
+            ),
+            _ => panic!("expect float, found {:?}", attr),
+        };
+
+        let result = collection
+            .fold(FoldOperation::STDDEV("i".into(), Estimator::Population))
+            .unwrap();
+        assert_near(&result.result[&big_group], 732.99334);
+        assert_near(&result.result[&small_group], 0f32);
+        let result = collection
+            .fold(FoldOperation::STDDEV("i".into(), Estimator::Sample))
+            .unwrap();
+        assert_near(&result.result[&big_group], 783.60284);
+        assert_eq!(result.result[&small_group], Attr::Null);
+        let result = collection
+            .fold(FoldOperation::VARIANCE("i".into(), Estimator::Population))
+            .unwrap();
+        assert_near(&result.result[&big_group], 537279.2);
+        let result = collection
+            .fold(FoldOperation::VARIANCE("i".into(), Estimator::Sample))
+            .unwrap();
+        assert_near(&result.result[&big_group], 614033.4);
+    }
+
+    #[test]
+    fn fold_many_at_once() {
+        let headers = ["userid", "time", "i", "s"];
+        let ctx = make_a_ctx();
+        let records: Vec<_> = [
+            vec!["0", "1", "233", "a"],
+            vec!["0", "1", "23", "b"],
+            vec!["0", "1", "2333", "c"],
+            vec!["1", "1", "0", "d"],
+            vec!["1", "1", "-28", "e"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let collection = Collection::new(records.iter().collect());
+
+        let results = collection
+            .fold_many(vec![
+                FoldOperation::SUM("i".into()),
+                FoldOperation::AVG("i".into()),
+                FoldOperation::COUNT,
+            ])
+            .unwrap();
+        assert_eq!(results.len(), 3);
+        for (op, expected) in zip(
+            [
+                FoldOperation::SUM("i".into()),
+                FoldOperation::AVG("i".into()),
+                FoldOperation::COUNT,
+            ],
+            results.iter(),
+        ) {
+            assert!(collection.fold(op).unwrap().result == expected.result);
+        }
+
+        let err = collection
+            .fold_many(vec![FoldOperation::COUNT, FoldOperation::SUM("s".into())])
+            .err()
+            .unwrap();
+        assert_eq!(
+            err,
+            FoldError {
+                op: "SUM",
+                attr_name: "s".into()
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "SUM operation should be performed on int or float"
+        );
+    }
+
+    #[test]
+    fn filter_with_compound_predicate() {
+        let headers = ["userid", "time", "i"];
+        let ctx = make_a_ctx();
+        let records: Vec<_> = [
+            vec!["0", "1", "233"],
+            vec!["0", "1", "23"],
+            vec!["0", "1", "2333"],
+            vec!["1", "1", "0"],
+            vec!["1", "1", "-28"],
+            vec!["1", "1", "233"],
+            vec!["2", "1", "366"],
+            vec!["2", "1", "243"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let cmp = |attr_name: &str, val: i32, op: CmpOp| {
+            Box::new(Predicate::Cmp {
+                attr_name: attr_name.into(),
+                val: Attr::Int(val),
+                op,
+            })
+        };
+        let record_count = |collection: &Collection| -> usize {
+            collection
+                .groups
+                .values()
+                .map(|group| group.records.len())
+                .sum()
+        };
+
+        // 200 < i < 2000
+        let conjunction = Collection::new(records.iter().collect()).filter_records(Predicate::And(
+            cmp("i", 200, CmpOp::Gt),
+            cmp("i", 2000, CmpOp::Lt),
+        ));
+        assert_eq!(record_count(&conjunction), 4);
+        assert_eq!(conjunction.groups.len(), 3);
+
+        // userid == 1 || i > 300
+        let disjunction = Collection::new(records.iter().collect()).filter_records(Predicate::Or(
+            cmp("userid", 1, CmpOp::Eq),
+            cmp("i", 300, CmpOp::Gt),
+        ));
+        assert_eq!(record_count(&disjunction), 5);
+
+        // !(userid == 0)
+        let negation = Collection::new(records.iter().collect())
+            .filter_records(Predicate::Not(cmp("userid", 0, CmpOp::Eq)));
+        assert_eq!(record_count(&negation), 5);
+        assert_eq!(negation.groups.len(), 2);
+    }
+
+    #[test]
+    fn filter_missing_attr() {
+        let ctx = make_a_ctx();
+        let records = [
+            Record::new(&ctx, vec![("userid", "0"), ("time", "1"), ("i", "-28")]).unwrap(),
+            Record::new(&ctx, vec![("userid", "0"), ("time", "1")]).unwrap(),
+        ];
+        let collection = Collection::new(records.iter().collect()).filter_records(FilterCond {
+            attr_name: "i".into(),
+            val: Attr::Int(0),
+            op: CmpOp::Lt,
+        });
+        let group = collection.groups.values().next().unwrap();
+        assert_eq!(group.records.len(), 1);
+        assert!(group.records.contains(&ByAddress(&records[0])));
+
+        let collection = Collection::new(records.iter().collect()).filter_records(Predicate::Not(
+            Box::new(Predicate::Cmp {
+                attr_name: "i".into(),
+                val: Attr::Int(0),
+                op: CmpOp::Lt,
+            }),
+        ));
+        let group = collection.groups.values().next().unwrap();
+        assert!(group.records.contains(&ByAddress(&records[1])));
+    }
+
+    #[test]
+    fn filter_str_predicates() {
+        let headers = ["userid", "time", "i", "s"];
+        let ctx = make_a_ctx();
+        let records: Vec<_> = [
+            vec!["0", "1", "1", "John Smith"],
+            vec!["0", "1", "2", "Smithers"],
+            vec!["0", "1", "3", "jane smith"],
+            vec!["0", "1", "4", "Doe"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let count = |predicate: Predicate| -> usize {
+            Collection::new(records.iter().collect())
+                .filter_records(predicate)
+                .groups
+                .values()
+                .map(|group| group.records.len())
+                .sum()
+        };
+
+        let contains = |case_insensitive| Predicate::Contains {
+            attr_name: "s".into(),
+            pattern: "smith".into(),
+            case_insensitive,
+        };
+        assert_eq!(count(contains(false)), 1);
+        assert_eq!(count(contains(true)), 3);
+        assert_eq!(
+            count(Predicate::StartsWith {
+                attr_name: "s".into(),
+                pattern: "SMITH".into(),
+                case_insensitive: true,
+            }),
+            1
+        );
+        assert_eq!(
+            count(Predicate::EndsWith {
+                attr_name: "s".into(),
+                pattern: "Smith".into(),
+                case_insensitive: false,
+            }),
+            1
+        );
+        // Non-string attributes never match
+        assert_eq!(
+            count(Predicate::Contains {
+                attr_name: "i".into(),
+                pattern: "1".into(),
+                case_insensitive: false,
+            }),
+            0
+        );
+    }
+
+    #[test]
+    fn write_collection_csv() {
+        let headers = ["userid", "time", "i", "b"];
+        let mut ctx = make_a_ctx();
+        ctx.set_nullable("b");
+        let records: Vec<_> = [
+            vec!["1", "1", "-28", "true"],
+            vec!["0", "1", "233", ""],
+            vec!["0", "1", "23", "false"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let collection = Collection::new(records.iter().collect());
+
+        let mut output = Vec::new();
+        collection
+            .write_csv(&mut output, &["i", "userid", "b", "x"])
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let mut lines = output.lines();
+        assert_eq!(lines.next(), Some("i,userid,b,x"));
+        let mut rows: Vec<&str> = lines.collect();
+        assert_eq!(rows.len(), 3);
+        // Rows of the same group are sorted and adjacent
+        let user_0 = rows.iter().position(|&row| row == "23,0,false,").unwrap();
+        assert_eq!(rows[user_0 + 1], "233,0,,");
+        rows.sort();
+        assert_eq!(rows, vec!["-28,1,true,", "23,0,false,", "233,0,,"]);
+
+        let mut again = Vec::new();
+        collection
+            .write_csv(&mut again, &["i", "userid", "b", "x"])
+            .unwrap();
+        assert_eq!(String::from_utf8(again).unwrap(), output);
+
+        let mut empty = Vec::new();
+        Collection::new(Vec::new())
+            .write_csv(&mut empty, &["i", "userid"])
+            .unwrap();
+        assert_eq!(String::from_utf8(empty).unwrap(), "i,userid\n");
+    }
+
+    #[test]
+    fn write_fold_result_csv() {
+        let headers = ["userid", "time", "i"];
+        let ctx = make_a_ctx();
+        let records: Vec<_> = [
+            vec!["0", "1", "233"],
+            vec!["0", "1", "23"],
+            vec!["0", "1", "2333"],
+            vec!["0", "1", "0"],
+            vec!["1", "1", "-28"],
+            vec!["1", "1", "233"],
+            vec!["2", "1", "366"],
+            vec!["2", "1", "243"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let collection = Collection::new(records.iter().collect());
+
+        for op in [
+            FoldOperation::AVG("i".into()),
+            FoldOperation::SUM("i".into()),
+            FoldOperation::COUNT,
+        ] {
+            let header = op.to_string();
+            let fold_result = collection.fold(op).unwrap();
+            let mut output = Vec::new();
+            fold_result.write_csv(&mut output).unwrap();
+
+            let mut rdr = csv::Reader::from_reader(output.as_slice());
+            assert_eq!(
+                rdr.headers().unwrap(),
+                vec!["time", "userid", header.as_str()]
+            );
+            let rows: Vec<csv::StringRecord> = rdr.records().map(|row| row.unwrap()).collect();
+            assert_eq!(rows.len(), 3);
+            // Rows are in the order of `labeled`, that is by group id
+            for (row, (key, attr)) in zip(&rows, fold_result.labeled()) {
+                assert_eq!(&row[0], key[0].1.to_string());
+                assert_eq!(&row[1], key[1].1.to_string());
+                let value = &row[2];
+                match attr {
+                    Attr::Float(v) => assert_eq!(value.parse::<f32>().unwrap(), v),
+                    Attr::Int(v) => assert_eq!(value.parse::<i32>().unwrap(), v),
+                    _ => panic!("unexpected fold result {:?}", attr),
+                }
+            }
+        }
+        let mut output = Vec::new();
+        let fold_result = collection.fold(FoldOperation::SUM("i".into())).unwrap();
+        fold_result.write_csv(&mut output).unwrap();
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .any(|line| line == "1,0,2589"));
+        let mut sorted = Vec::new();
+        fold_result
+            .write_csv_sorted_by_key(&mut sorted, None)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(sorted).unwrap(),
+            "time,userid,SUM(i)\n1,0,2589\n1,1,205\n1,2,609\n"
+        );
+        assert_eq!(FoldOperation::AVG("i".into()).to_string(), "AVG(i)");
+        assert_eq!(
+            FoldOperation::STDDEV("i".into(), Estimator::Sample).to_string(),
+            "STDDEV_SAMP(i)"
+        );
+    }
+
+    #[test]
+    fn infer_ctx() {
+        let headers = ["i", "s", "f", "b", "l", "n", "e"];
+        let sample_rows = vec![
+            vec!["1", "1", "0.5", "true", "1700000000000", "", ""],
+            vec!["2", "x", "1", "F", "1", "3", ""],
+            vec!["3", "2", "-2", "t", "2", "NA", ""],
+        ];
+        let ctx = Ctx::infer(&headers, &sample_rows);
+        assert_eq!(ctx.attr_type["i"], Attr::Int(0));
+        assert_eq!(ctx.attr_type["s"], Attr::Str(String::new()));
+        assert_eq!(ctx.attr_type["f"], Attr::Float(0f32));
+        assert_eq!(ctx.attr_type["b"], Attr::Bool(false));
+        assert_eq!(ctx.attr_type["l"], Attr::Int64(0));
+        assert_eq!(ctx.attr_type["n"], Attr::Int(0));
+        assert_eq!(ctx.attr_type["e"], Attr::Str(String::new()));
+        assert!(ctx.nullable.contains("n"));
+        assert!(!ctx.nullable.contains("i"));
+
+        let records: Vec<_> = sample_rows
+            .into_iter()
+            .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+            .collect();
+        assert_eq!(records[2].attrs["n"], Attr::Null);
+    }
+
+    #[test]
+    fn datetime_attr() {
+        let mut ctx = Ctx::new();
+        ctx.add_attr_type("at", Attr::DateTime(NaiveDateTime::default()), None);
+        ctx.add_attr_type(
+            "day",
+            Attr::DateTime(NaiveDateTime::default()),
+            Some(ComponentRule::Interval(Interval {
+                start: 0,
+                step: 24 * 3600,
+            })),
+        );
+        ctx.set_datetime_format("day", "%Y/%m/%d %H:%M");
+        let headers = ["at", "day"];
+        let records: Vec<_> = [
+            vec!["2024-01-15T08:30:00Z", "2024/01/15 08:30"],
+            vec!["2024-01-15T10:30:00+02:00", "2024/01/15 23:59"],
+            vec!["2024-01-16T00:00:00Z", "2024/01/16 00:00"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+
+        assert_eq!(records[0].attrs["at"], records[1].attrs["at"]);
+        assert_eq!(records[0].attrs["at"].to_string(), "2024-01-15T08:30:00");
+        assert!(records[0].attrs["at"] < records[2].attrs["at"]);
+        assert_eq!(records[0].group_id, records[1].group_id);
+        assert_ne!(records[1].group_id, records[2].group_id);
+
+        let collection = Collection::new(records.iter().collect()).filter_records(Predicate::Cmp {
+            attr_name: "at".into(),
+            val: Attr::DateTime(
+                NaiveDateTime::parse_from_str("2024-01-15 12:00", "%Y-%m-%d %H:%M").unwrap(),
+            ),
+            op: CmpOp::Gt,
+        });
+        assert_eq!(collection.groups.len(), 1);
+
+        assert!(matches!(
+            Attr::new(&ctx, "day", "2024-01-15"),
+            Err(Error::Parse(ParseError {
+                expected: "datetime",
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn interval_negative_values() {
+        let key = |ctx_attr: Attr, interval: Interval, raw_attr: &str| -> Attr {
+            let mut ctx = Ctx::new();
+            ctx.add_attr_type("v", ctx_attr, Some(ComponentRule::Interval(interval)));
+            Record::new(&ctx, vec![("v", raw_attr)]).unwrap().group_key[0]
+                .1
+                .clone()
+        };
+
+        let keys: Vec<Attr> = ["-5", "-10", "-11", "5", "0"]
+            .into_iter()
+            .map(|raw_attr| key(Attr::Int(0), Interval::new(0, 10), raw_attr))
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                Attr::Int(-10),
+                Attr::Int(-10),
+                Attr::Int(-20),
+                Attr::Int(0),
+                Attr::Int(0)
+            ]
+        );
+        assert_eq!(
+            key(Attr::Int64(0), Interval::new(1, 3), "-1"),
+            Attr::Int64(-2)
+        );
+        let keys: Vec<Attr> = ["-5.5", "-0.5", "5.5"]
+            .into_iter()
+            .map(|raw_attr| key(Attr::Float(0f32), Interval::new(0, 10), raw_attr))
+            .collect();
+        assert_eq!(
+            keys,
+            [Attr::Float(-10.0), Attr::Float(-10.0), Attr::Float(0.0)]
+        );
+
+        // No overflow at the edges of the value types
+        assert_eq!(
+            key(Attr::Int(0), Interval::new(-10, 10), "2147483647"),
+            Attr::Int(2147483640)
+        );
+        assert_eq!(
+            key(Attr::Int(0), Interval::new(0, 10), "-2147483648"),
+            Attr::Int(i32::MIN)
+        );
+        assert_eq!(
+            key(
+                Attr::Int64(0),
+                Interval::new(-10, 10),
+                &i64::MAX.to_string()
+            ),
+            Attr::Int64(9223372036854775800)
+        );
+        assert_eq!(
+            key(Attr::Float(0f32), Interval::new(0, 10), "3e10"),
+            Attr::Float(3e10)
+        );
+    }
+
+    #[test]
+    fn datetime_interval_before_start() {
+        let midnight = NaiveDateTime::parse_from_str("2024-01-15 00:00", "%Y-%m-%d %H:%M").unwrap();
+        let mut ctx = Ctx::new();
+        ctx.add_attr_type(
+            "at",
+            Attr::DateTime(NaiveDateTime::default()),
+            Some(ComponentRule::Interval(Interval::new(
+                midnight.and_utc().timestamp() as i32,
+                24 * 3600,
+            ))),
+        );
+        let keys: Vec<Attr> = [
+            "2024-01-14T01:00:00Z",
+            "2024-01-14T23:00:00Z",
+            "2024-01-15T01:00:00Z",
+        ]
+        .into_iter()
+        .map(|raw_attr| {
+            Record::new(&ctx, vec![("at", raw_attr)]).unwrap().group_key[0]
+                .1
+                .clone()
+        })
+        .collect();
+        let day_before = Attr::DateTime(midnight - Duration::days(1));
+        assert_eq!(
+            keys,
+            [day_before.clone(), day_before, Attr::DateTime(midnight)]
+        );
+
+        assert!(Interval::try_new(0, 0).is_none());
+        assert!(Interval::try_new(0, -1).is_none());
+        assert!(Interval::try_new(-6, 7).is_some());
+    }
+
+    #[test]
+    fn time_bucket_rule() {
+        let midnight = NaiveDateTime::parse_from_str("2024-01-15 00:00", "%Y-%m-%d %H:%M").unwrap();
+        let make_ctx = |size: Duration, origin: NaiveDateTime| {
+            let mut ctx = Ctx::new();
+            ctx.add_attr_type(
+                "at",
+                Attr::DateTime(NaiveDateTime::default()),
+                Some(ComponentRule::TimeBucket(TimeBucket::new(size, origin))),
+            );
+            ctx
+        };
+        let group_ids = |ctx: &Ctx, raw_attrs: &[&str]| -> Vec<u64> {
+            raw_attrs
+                .iter()
+                .map(|&raw_attr| Record::new(ctx, vec![("at", raw_attr)]).unwrap().group_id)
+                .collect()
+        };
+
+        let hourly = make_ctx(Duration::hours(1), midnight);
+        let ids = group_ids(
+            &hourly,
+            &[
+                "2024-01-15T08:00:00Z",
+                "2024-01-15T08:59:59Z",
+                "2024-01-15T09:00:00Z",
+                "2024-01-14T23:30:00Z",
+                "2024-01-14T23:59:59Z",
+            ],
+        );
+        assert_eq!(ids[0], ids[1]);
+        assert_ne!(ids[1], ids[2]);
+        assert_eq!(ids[3], ids[4]);
+        assert_ne!(ids[3], ids[0]);
+
+        // Daily buckets aligned to 06:00 instead of midnight
+        let daily = make_ctx(Duration::days(1), midnight + Duration::hours(6));
+        let ids = group_ids(
+            &daily,
+            &[
+                "2024-01-15T05:59:59Z",
+                "2024-01-15T06:00:00Z",
+                "2024-01-16T05:00:00Z",
+            ],
+        );
+        assert_ne!(ids[0], ids[1]);
+        assert_eq!(ids[1], ids[2]);
+
+        assert!(TimeBucket::try_new(Duration::zero(), midnight).is_none());
+        assert!(TimeBucket::try_new(Duration::microseconds(999), midnight).is_none());
+        assert!(TimeBucket::try_new(Duration::hours(-1), midnight).is_none());
+        assert!(TimeBucket::try_new(Duration::milliseconds(1), midnight).is_some());
+    }
+
+    #[test]
+    fn prefix_rule() {
+        let group_ids = |len: usize, raw_attrs: &[&str]| -> Vec<u64> {
+            let mut ctx = Ctx::new();
+            ctx.add_attr_type(
+                "s",
+                Attr::Str(String::new()),
+                Some(ComponentRule::Prefix { len }),
+            );
+            raw_attrs
+                .iter()
+                .map(|&raw_attr| Record::new(&ctx, vec![("s", raw_attr)]).unwrap().group_id)
+                .collect()
+        };
+
+        let ids = group_ids(3, &["hello", "help", "he"]);
+        assert_eq!(ids[0], ids[1]);
+        assert_ne!(ids[0], ids[2]);
+        let ids = group_ids(4, &["hello", "help"]);
+        assert_ne!(ids[0], ids[1]);
+        // Chars rather than bytes
+        let ids = group_ids(2, &["日本語", "日本", "日"]);
+        assert_eq!(ids[0], ids[1]);
+        assert_ne!(ids[1], ids[2]);
+    }
+
+    #[test]
+    fn difference_keeps_self_only_groups() {
+        let headers = ["userid", "time", "i"];
+        let ctx = make_a_ctx();
+        let records: Vec<_> = [
+            vec!["0", "1", "233"],
+            vec!["0", "1", "23"],
+            vec!["1", "1", "0"],
+            vec!["1", "1", "-28"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let whole = Collection::new(records.iter().collect());
+        let user_0 = Collection::new(vec![&records[0]]);
+
+        let difference = whole.difference(&user_0);
+        assert_eq!(difference.groups.len(), 2);
+        assert_eq!(difference.groups[&records[0].group_id].records.len(), 1);
+        assert_eq!(difference.groups[&records[2].group_id].records.len(), 2);
+    }
+
+    #[test]
+    fn symmetric_difference() {
+        let headers = ["userid", "time", "i"];
+        let ctx = make_a_ctx();
+        let records: Vec<_> = [
+            vec!["0", "1", "233"],
+            vec!["0", "1", "23"],
+            vec!["1", "1", "0"],
+            vec!["2", "1", "-28"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let a = Collection::new(records[..3].iter().collect());
+        let b = Collection::new(vec![&records[1], &records[3]]);
+
+        assert!(a.clone().symmetric_difference(&a).groups.is_empty());
+
+        let result = a.symmetric_difference(&b);
+        assert_eq!(result.groups.len(), 3);
+        assert!(result.groups[&records[0].group_id]
+            .records
+            .contains(&ByAddress(&records[0])));
+        assert_eq!(result.groups[&records[0].group_id].records.len(), 1);
+        assert_eq!(result.groups[&records[2].group_id].records.len(), 1);
+        assert_eq!(result.groups[&records[3].group_id].records.len(), 1);
+    }
+
+    #[test]
+    fn subset_and_superset() {
+        let headers = ["userid", "time", "i"];
+        let ctx = make_a_ctx();
+        let records: Vec<_> = [
+            vec!["0", "1", "233"],
+            vec!["0", "1", "23"],
+            vec!["0", "1", "2333"],
+            vec!["0", "1", "0"],
+            vec!["0", "1", "-28"],
+            vec!["0", "1", "233"],
+            vec!["0", "1", "366"],
+            vec!["0", "1", "243"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let whole_view = Collection::new(records.iter().collect());
+        let end_with_3_view = Collection::new(
+            records
+                .iter()
+                .filter(|record| matches!(record.attrs.get("i"), Some(Attr::Int(x)) if x % 10 == 3))
+                .collect(),
+        );
+
+        assert!(end_with_3_view.is_subset(&whole_view));
+        assert!(whole_view.is_superset(&end_with_3_view));
+        assert!(!whole_view.is_subset(&end_with_3_view));
+        assert!(whole_view.is_subset(&whole_view));
+
+        let other_record = Record::new(&ctx, vec![("userid", "1"), ("time", "1")]).unwrap();
+        let other_group = Collection::new(vec![&other_record]);
+        assert!(!other_group.is_subset(&whole_view));
+        assert!(Collection::new(Vec::new()).is_subset(&whole_view));
+    }
+
+    #[test]
+    fn iterate_groups_and_records() {
+        let headers = ["userid", "time", "i"];
+        let ctx = make_a_ctx();
+        let records: Vec<_> = [
+            vec!["0", "1", "233"],
+            vec!["0", "1", "23"],
+            vec!["1", "1", "0"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let collection = Collection::new(records.iter().collect());
+
+        assert_eq!(collection.groups().count(), 2);
+        let group = collection
+            .groups()
+            .find(|group| group.id() == records[0].group_id)
+            .unwrap();
+        let mut values: Vec<&Attr> = group.records().map(|record| &record.attrs["i"]).collect();
+        values.sort_by(|x, y| x.partial_cmp(y).unwrap());
+        assert_eq!(values, vec![&Attr::Int(23), &Attr::Int(233)]);
+        assert!(group
+            .records()
+            .any(|record| std::ptr::eq(record, &records[0])));
+    }
+
+    #[test]
+    fn group_key() {
+        let headers = ["userid", "time"];
+        let ctx = make_a_ctx();
+        let records: Vec<_> = [vec!["0", "2.9"], vec!["0", "3.9"], vec!["1", "8"]]
+            .into_iter()
+            .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+            .collect();
+        let collection = Collection::new(records.iter().collect());
+        let sorted_key = |id: u64| {
+            let mut key = collection.groups[&id].key().to_vec();
+            key.sort_by(|x, y| x.0.cmp(&y.0));
+            key
+        };
+
+        assert_eq!(collection.group_count(), 2);
+        assert_eq!(
+            sorted_key(records[0].group_id),
+            vec![
+                ("time".to_string(), Attr::Float(1f32)),
+                ("userid".to_string(), Attr::Int(0))
+            ]
+        );
+        assert_eq!(
+            sorted_key(records[2].group_id),
+            vec![
+                ("time".to_string(), Attr::Float(7f32)),
+                ("userid".to_string(), Attr::Int(1))
+            ]
+        );
+    }
+
+    #[test]
+    fn group_id_collision() {
+        let headers = ["userid", "time"];
+        let ctx = make_a_ctx();
+        let records: Vec<_> = [vec!["0", "1"], vec!["0", "2"], vec!["1", "1"]]
+            .into_iter()
+            .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+            .collect();
+        // Same key always shares a group
+        let collection = Collection::try_new(records.iter().collect()).unwrap();
+        assert_eq!(collection.group_count(), 2);
+        assert_eq!(collection.groups[&records[0].group_id].records.len(), 2);
+
+        // Forge a record whose key differs while its group id collides
+        let forged = Record {
+            attrs: IndexMap::new(),
+            group_id: records[0].group_id,
+            group_key: records[2].group_key.clone(),
+        };
+        let err = Collection::try_new(vec![&records[0], &forged])
+            .err()
+            .unwrap();
+        assert_eq!(err.id, records[0].group_id);
+        assert_eq!(err.key, records[0].group_key);
+        assert_eq!(err.other_key, records[2].group_key);
+    }
+
+    #[test]
+    fn preserve_attr_order() {
+        let headers = ["s", "userid", "b", "time", "i"];
+        let ctx = make_a_ctx();
+        let record =
+            Record::new(&ctx, zip(headers, ["x", "0", "true", "1", "2"]).collect()).unwrap();
+        assert_eq!(
+            record.attrs.keys().map(|x| x.as_str()).collect::<Vec<_>>(),
+            headers
+        );
+        assert_eq!(
+            ctx.attr_type.keys().map(|x| x.as_str()).collect::<Vec<_>>(),
+            vec!["userid", "time", "i", "f", "b", "s"]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_attr() {
+        let cases = [
+            (Attr::Int(3), "3"),
+            (Attr::Int64(1_700_000_000_000), "1700000000000"),
+            (Attr::Float(0.5), "0.5"),
+            (Attr::Bool(true), "true"),
+            (Attr::Str("x".into()), "\"x\""),
+            (Attr::Null, "null"),
+        ];
+        for (attr, json) in cases {
+            assert_eq!(serde_json::to_string(&attr).unwrap(), json);
+            assert_eq!(serde_json::from_str::<Attr>(json).unwrap(), attr);
+        }
+
+        let datetime = Attr::DateTime(
+            NaiveDateTime::parse_from_str("2024-01-15 08:30", "%Y-%m-%d %H:%M").unwrap(),
+        );
+        assert_eq!(
+            serde_json::to_string(&datetime).unwrap(),
+            "\"2024-01-15T08:30:00\""
+        );
+        assert_eq!(
+            serde_json::from_str::<Attr>("\"2024-01-15T08:30:00\"").unwrap(),
+            Attr::Str("2024-01-15T08:30:00".into())
+        );
+    }
+
+    #[test]
+    fn sort_attrs() {
+        let mut attrs = vec![
+            Attr::Null,
+            Attr::Str("b".into()),
+            Attr::Float(f32::NAN),
+            Attr::Float(0f32),
+            Attr::Int(5),
+            Attr::Bool(true),
+            Attr::Float(-0f32),
+            Attr::Str("a".into()),
+            Attr::Float(-1.5),
+            Attr::Int64(-7),
+            Attr::Int(-3),
+            Attr::Bool(false),
+        ];
+        attrs.sort();
+        assert_eq!(
+            attrs,
+            vec![
+                Attr::Int(-3),
+                Attr::Int(5),
+                Attr::Int64(-7),
+                Attr::Float(-1.5),
+                Attr::Float(-0f32),
+                Attr::Float(0f32),
+                Attr::Float(f32::NAN),
+                Attr::Bool(false),
+                Attr::Bool(true),
+                Attr::Str("a".into()),
+                Attr::Str("b".into()),
+                Attr::Null,
+            ]
+        );
+        assert_eq!(Attr::Float(f32::NAN), Attr::Float(f32::NAN));
+        assert_ne!(Attr::Float(0f32), Attr::Float(-0f32));
+
+        let distinct: HashSet<Attr> = [Attr::Float(1f32), Attr::Float(1f32), Attr::Int(1)]
+            .into_iter()
+            .collect();
+        assert_eq!(distinct.len(), 2);
+    }
+
+    #[test]
+    fn sort_records_within_group() {
+        let headers = ["userid", "time", "i"];
+        let ctx = make_a_ctx();
+        let mut records: Vec<_> = [
+            vec!["0", "1", "233"],
+            vec!["0", "1", "23"],
+            vec!["0", "1", "2333"],
+            vec!["0", "1", "0"],
+            vec!["0", "1", "-28"],
+            vec!["0", "1", "233"],
+            vec!["0", "1", "366"],
+            vec!["0", "1", "243"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        records.push(Record::new(&ctx, vec![("userid", "0"), ("time", "1")]).unwrap());
+        let collection = Collection::new(records.iter().collect());
+        let values = |ascending: bool| -> Vec<Option<&Attr>> {
+            let sorted = collection.sorted_records("i", ascending);
+            assert_eq!(sorted.len(), 1);
+            sorted[0]
+                .1
+                .iter()
+                .map(|record| record.attrs.get("i"))
+                .collect()
+        };
+
+        let ints = |xs: &[i32]| -> Vec<Attr> { xs.iter().map(|&x| Attr::Int(x)).collect() };
+        let ascending = ints(&[-28, 0, 23, 233, 233, 243, 366, 2333]);
+        let mut expected: Vec<Option<&Attr>> = ascending.iter().map(Some).collect();
+        expected.push(None);
+        assert_eq!(values(true), expected);
+
+        let descending = ints(&[2333, 366, 243, 233, 233, 23, 0, -28]);
+        let mut expected: Vec<Option<&Attr>> = descending.iter().map(Some).collect();
+        expected.push(None);
+        assert_eq!(values(false), expected);
+    }
+
+    #[test]
+    fn top_n_per_group() {
+        use std::cmp::Reverse;
+
+        let headers = ["userid", "time", "i"];
+        let ctx = make_a_ctx();
+        let records: Vec<_> = [
+            vec!["0", "1", "233"],
+            vec!["0", "1", "23"],
+            vec!["0", "1", "2333"],
+            vec!["0", "1", "0"],
+            vec!["0", "1", "-28"],
+            vec!["0", "1", "233"],
+            vec!["0", "1", "366"],
+            vec!["0", "1", "243"],
+            vec!["1", "1", "7"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let collection = Collection::new(records.iter().collect());
+        // groups are ordered by id, which is a hash, so sort them by their values instead
+        let values = |collection: &Collection, ascending: bool| -> Vec<Vec<Attr>> {
+            let mut groups: Vec<Vec<Attr>> = collection
+                .sorted_records("i", ascending)
+                .into_iter()
+                .map(|(_, records)| records.iter().map(|r| r.attrs["i"].clone()).collect())
+                .collect();
+            groups.sort_by_key(|values| Reverse(values.len()));
+            groups
+        };
+
+        let top = collection.clone().top_n("i", 4, false);
+        assert_eq!(top.record_count(), 5);
+        assert_eq!(
+            values(&top, false),
+            vec![
+                vec![
+                    Attr::Int(2333),
+                    Attr::Int(366),
+                    Attr::Int(243),
+                    Attr::Int(233)
+                ],
+                vec![Attr::Int(7)],
+            ]
+        );
+
+        let bottom = collection.clone().top_n("i", 2, true);
+        assert_eq!(
+            values(&bottom, true),
+            vec![vec![Attr::Int(-28), Attr::Int(0)], vec![Attr::Int(7)]]
+        );
+
+        assert_eq!(collection.clone().top_n("i", 100, true).record_count(), 9);
+        assert!(collection.top_n("i", 0, true).is_empty());
+    }
+
+    #[test]
+    fn inner_join_collections() {
+        let mut ctx = Ctx::new();
+        ctx.add_attr_type("userid", Attr::Int(0), None);
+        ctx.add_attr_type("name", Attr::Str("".into()), None);
+        ctx.add_attr_type("event", Attr::Str("".into()), None);
+        ctx.set_nullable("userid");
+
+        let users: Vec<_> = [vec!["1", "alice"], vec!["2", "bob"], vec!["3", "carol"]]
+            .into_iter()
+            .map(|raw_record| {
+                Record::new(&ctx, zip(["userid", "name"], raw_record).collect()).unwrap()
+            })
+            .collect();
+        let events: Vec<_> = [
+            vec!["1", "login", "a"],
+            vec!["1", "logout", "b"],
+            vec!["2", "login", "c"],
+            vec!["4", "login", "d"],
+            vec!["NA", "login", "e"],
+        ]
+        .into_iter()
+        .map(|raw_record| {
+            Record::new(&ctx, zip(["userid", "event", "name"], raw_record).collect()).unwrap()
+        })
+        .collect();
+        let users = Collection::new(users.iter().collect());
+        let events = Collection::new(events.iter().collect());
+
+        let joined = users.inner_join(&events, "userid");
+        assert_eq!(joined.len(), 3);
+        let names: Vec<&str> = joined[0].attrs().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["userid", "name", "event", "name_right"]);
+        let rows: Vec<Vec<String>> = joined
+            .iter()
+            .map(|record| record.attrs().map(|(_, attr)| attr.to_string()).collect())
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                vec!["1", "alice", "login", "a"],
+                vec!["1", "alice", "logout", "b"],
+                vec!["2", "bob", "login", "c"],
+            ]
+        );
+        assert_eq!(joined[2].get("name_right"), Some(&Attr::Str("c".into())));
+        assert_eq!(joined[2].get("missing"), None);
+        let mut output = Vec::new();
+        JoinedRecord::write_csv(&joined, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "userid,name,event,name_right\n1,alice,login,a\n1,alice,logout,b\n2,bob,login,c\n"
+        );
+        let mut empty = Vec::new();
+        JoinedRecord::write_csv(&[], &mut empty).unwrap();
+        assert!(empty.is_empty());
+
+        // a record without the join key never matches
+        let no_key = [Record::new(&ctx, vec![("name", "dave")]).unwrap()];
+        assert!(Collection::new(no_key.iter().collect())
+            .inner_join(&events, "userid")
+            .is_empty());
+    }
+
+    #[test]
+    fn filter_groups_having() {
+        let headers = ["userid", "time", "i"];
+        let ctx = make_a_ctx();
+        let records: Vec<_> = [
+            // group_a
+            vec!["0", "1.1", "1"],
+            vec!["0", "2.9", "2"],
+            vec!["0", "3", "3"],
+            vec!["0", "3.9", "4"],
+            // group_b
+            vec!["0", "7.9", "100"],
+            vec!["0", "9", "200"],
+            // group_c
+            vec!["1", "4.1", "5"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let collection = Collection::new(records.iter().collect());
+
+        let having = collection
+            .clone()
+            .filter_groups_by_fold(FoldOperation::COUNT, CmpOp::Gt, Attr::Int(1))
+            .unwrap();
+        assert_eq!(having.group_count(), 2);
+        assert_eq!(having.record_count(), 6);
+        let having = collection
+            .clone()
+            .filter_groups_by_fold(FoldOperation::COUNT, CmpOp::Ne, Attr::Int(2))
+            .unwrap();
+        assert_eq!(having.group_count(), 2);
+        assert_eq!(having.record_count(), 5);
+
+        // float results against an int threshold
+        let having = collection
+            .clone()
+            .filter_groups_by_fold(FoldOperation::SUM("i".into()), CmpOp::Le, Attr::Int(5))
+            .unwrap();
+        assert_eq!(having.group_count(), 1);
+        assert_eq!(having.record_count(), 1);
+
+        let having = collection
+            .filter_groups_by_fold(FoldOperation::COUNT, CmpOp::Gt, Attr::Int(4))
+            .unwrap();
+        assert!(having.is_empty());
+    }
+
+    #[test]
+    fn computed_attribute() {
+        let mut ctx = Ctx::new();
+        ctx.add_attr_type("price", Attr::Int(0), None);
+        ctx.add_attr_type("quantity", Attr::Int(0), None);
+        ctx.add_computed("total", Some(ComponentRule::Unique), |attrs| {
+            match (attrs.get("price"), attrs.get("quantity")) {
+                (Some(Attr::Int(price)), Some(Attr::Int(quantity))) => Attr::Int(price * quantity),
+                _ => Attr::Null,
+            }
+        });
+
+        let records: Vec<_> = [vec!["3", "4"], vec!["2", "6"], vec!["5", "1"]]
+            .into_iter()
+            .map(|raw_record| {
+                Record::new(&ctx, zip(["price", "quantity"], raw_record).collect()).unwrap()
+            })
+            .collect();
+        assert_eq!(records[0].attrs.get("total"), Some(&Attr::Int(12)));
+        assert_eq!(records[2].attrs.get("total"), Some(&Attr::Int(5)));
+        assert_eq!(records[0].group_key, vec![("total".into(), Attr::Int(12))]);
+        assert_eq!(records[0].group_id, records[1].group_id);
+        assert_ne!(records[0].group_id, records[2].group_id);
+        assert_eq!(Collection::new(records.iter().collect()).group_count(), 2);
+
+        let record = Record::new(&ctx, vec![("price", "3")]).unwrap();
+        assert_eq!(record.attrs.get("total"), Some(&Attr::Null));
+    }
+
+    #[test]
+    fn render_ascii_table() {
+        let headers = ["userid", "time", "s"];
+        let ctx = make_a_ctx();
+        let records: Vec<_> = [
+            vec!["0", "1.5", "hello"],
+            vec!["0", "2", "a"],
+            vec!["10", "1", "hi"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let collection = Collection::new(records.iter().collect());
+        let userid_0 = "| 0      | 1.5  | hello |\n| 0      | 2    | a     |\n";
+        let userid_10 = "| 10     | 1    | hi    |\n";
+        let (first, second) = if records[0].group_id < records[2].group_id {
+            (userid_0, userid_10)
+        } else {
+            (userid_10, userid_0)
+        };
+        let border = "+--------+------+-------+\n";
+        let expected = [
+            border,
+            "| userid | time | s     |\n",
+            "+========+======+=======+\n",
+            first,
+            border,
+            second,
+            border,
+        ]
+        .concat();
+        assert_eq!(collection.to_ascii_table(&headers), expected);
+
+        let empty = Collection::new(vec![]).to_ascii_table(&["userid"]);
+        assert_eq!(empty, "+--------+\n| userid |\n+========+\n");
+    }
+
+    #[test]
+    fn fold_result_to_markdown() {
+        let mut ctx = Ctx::new();
+        ctx.add_attr_type("userid", Attr::Int(0), Some(ComponentRule::Unique));
+        ctx.add_attr_type("i", Attr::Int(0), None);
+        let records: Vec<_> = [vec!["0", "1"], vec!["0", "2"], vec!["7", "5"]]
+            .into_iter()
+            .map(|raw_record| {
+                Record::new(&ctx, zip(["userid", "i"], raw_record).collect()).unwrap()
+            })
+            .collect();
+        let collection = Collection::new(records.iter().collect());
+        let markdown = collection
+            .fold(FoldOperation::SUM("i".into()))
+            .unwrap()
+            .to_markdown();
+
+        let lines: Vec<&str> = markdown.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], "| userid | SUM(i) |");
+        assert_eq!(lines[1], "| --- | --- |");
+        let mut rows = lines[2..].to_vec();
+        rows.sort();
+        assert_eq!(rows, vec!["| 0 | 3 |", "| 7 | 5 |"]);
+        // every line is a row of the same number of cells
+        assert!(lines.iter().all(|line| line.starts_with('|')
+            && line.ends_with('|')
+            && line.matches('|').count() == 3));
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_fold_matches_sequential() {
+        let headers = ["userid", "time", "i", "f"];
+        let ctx = make_a_ctx();
+        let records: Vec<_> = (0..1000)
+            .map(|x| {
+                let raw_record = [
+                    (x % 17).to_string(),
+                    (x % 23).to_string(),
+                    (x * 7 % 101).to_string(),
+                    (x as f32 / 8.0).to_string(),
+                ];
+                Record::new(
+                    &ctx,
+                    zip(headers, raw_record.iter().map(|x| x.as_str())).collect(),
+                )
+                .unwrap()
+            })
+            .collect();
+        let collection = Collection::new(records.iter().collect());
+        let ops = || {
+            vec![
+                FoldOperation::SUM("i".into()),
+                FoldOperation::AVG("f".into()),
+                FoldOperation::COUNT,
+                FoldOperation::COUNT_DISTINCT("i".into()),
+                FoldOperation::STDDEV("f".into(), Estimator::Sample),
+            ]
+        };
+        let sequential = collection.fold_many(ops()).unwrap();
+        let parallel = collection.par_fold_many(ops()).unwrap();
+        assert_eq!(sequential.len(), parallel.len());
+        for (x, y) in zip(&sequential, &parallel) {
+            assert!(x.result == y.result);
+        }
+
+        let with_str =
+            [Record::new(&ctx, vec![("userid", "0"), ("time", "1"), ("s", "x")]).unwrap()];
+        assert_eq!(
+            Collection::new(with_str.iter().collect())
+                .par_fold_many(vec![FoldOperation::AVG("s".into())])
+                .err(),
+            Some(FoldError {
+                op: "AVG",
+                attr_name: "s".into()
+            })
+        );
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_construct_collection() {
+        let headers = ["userid", "time"];
+        let ctx = make_a_ctx();
+        let records: Vec<_> = [
+            vec!["0", "1.1"],
+            vec!["0", "2.9"],
+            vec!["0", "3"],
+            vec!["0", "3.9"],
+            vec!["0", "7.9"],
+            vec!["0", "9"],
+            vec!["1", "4.1"],
+            vec!["1", "6.9"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let sequential = Collection::new(records.iter().collect());
+        let parallel = Collection::par_new(records.iter().collect());
+        assert_eq!(parallel.group_count(), 3);
+        assert_eq!(parallel.group_count(), sequential.group_count());
+        assert_eq!(parallel.record_count(), sequential.record_count());
+        assert!(parallel.is_subset(&sequential) && sequential.is_subset(&parallel));
+
+        let many: Vec<_> = (0..10000)
+            .map(|x| {
+                let raw_record = [(x % 13).to_string(), (x % 31).to_string()];
+                Record::new(
+                    &ctx,
+                    zip(headers, raw_record.iter().map(|x| x.as_str())).collect(),
+                )
+                .unwrap()
+            })
+            .collect();
+        let sequential = Collection::new(many.iter().collect());
+        let parallel = Collection::par_new(many.iter().collect());
+        assert_eq!(parallel.group_count(), sequential.group_count());
+        assert_eq!(parallel.record_count(), 10000);
+        assert!(parallel.is_subset(&sequential) && sequential.is_subset(&parallel));
+    }
+
+    #[test]
+    fn build_collection_incrementally() {
+        let headers = ["userid", "time"];
+        let ctx = make_a_ctx();
+        let records: Vec<_> = [
+            vec!["0", "1.1"],
+            vec!["0", "2.9"],
+            vec!["0", "3"],
+            vec!["0", "3.9"],
+            vec!["0", "7.9"],
+            vec!["0", "9"],
+            vec!["1", "4.1"],
+            vec!["1", "6.9"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+
+        let mut builder = CollectionBuilder::new();
+        for record in records.iter() {
+            builder.push(record).unwrap();
+        }
+        let built = builder.finish();
+        let bulk = Collection::new(records.iter().collect());
+        assert_eq!(built.group_count(), 3);
+        assert_eq!(built.record_count(), bulk.record_count());
+        assert!(built.is_subset(&bulk) && bulk.is_subset(&built));
+
+        assert!(CollectionBuilder::new().finish().is_empty());
+    }
+
+    #[test]
+    fn trim_before_parsing() {
+        let headers = ["userid", "time", "i", "b", "s"];
+        let mut ctx = make_a_ctx();
+        ctx.set_nullable("i");
+        let record = Record::new(
+            &ctx,
+            zip(headers, [" 23 ", "1", "\t-8", "true ", " hi "]).collect(),
+        )
+        .unwrap();
+        assert_eq!(record.attrs["userid"], Attr::Int(23));
+        assert_eq!(record.attrs["i"], Attr::Int(-8));
+        assert_eq!(record.attrs["b"], Attr::Bool(true));
+        assert_eq!(record.attrs["s"], Attr::Str(" hi ".into()));
+        let record =
+            Record::new(&ctx, zip(headers, ["0", "1", " NA ", "f", ""]).collect()).unwrap();
+        assert_eq!(record.attrs["i"], Attr::Null);
+
+        ctx.set_trim_strings(true);
+        let record =
+            Record::new(&ctx, zip(headers, ["0", "1", "0", "f", " hi "]).collect()).unwrap();
+        assert_eq!(record.attrs["s"], Attr::Str("hi".into()));
+
+        ctx.set_trim(false);
+        assert!(matches!(
+            Record::new(&ctx, zip(headers, [" 23 ", "1", "0", "f", ""]).collect()),
+            Err(Error::Parse(e)) if e == ParseError {
+                header: "userid".into(),
+                raw_attr: " 23 ".into(),
+                expected: "int"
+            }
+        ));
+
+        let ctx = Ctx::infer(&["i"], &[vec![" 1"], vec!["2 "]]);
+        assert_eq!(ctx.attr_type["i"], Attr::Int(0));
+    }
+
+    #[test]
+    fn check_type_consistency() {
+        let headers = ["userid", "time", "i", "unknown"];
+        let ctx = make_a_ctx();
+        let rows = vec![
+            vec!["0", "1", "2", "x"],
+            vec!["1", "1", "hello", "y"],
+            vec!["2", "1", "3", "z"],
+            vec!["oops", "1", "true", "w"],
+        ];
+        let conflicts = ctx.check_consistency(&headers, &rows);
+        let found: Vec<(usize, &str, &str)> = conflicts
+            .iter()
+            .map(|x| (x.row, x.error.header.as_str(), x.error.raw_attr.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![(1, "i", "hello"), (3, "userid", "oops"), (3, "i", "true")]
+        );
+        assert_eq!(
+            conflicts[0].to_string(),
+            "row 1: Error: expect int when parsing attribute `i', which value is `hello'"
+        );
+        assert!(ctx.check_consistency(&headers, &rows[..1]).is_empty());
+    }
+
+    #[test]
+    fn group_by_normalized_str() {
+        let records = |lowercase: bool, trim: bool| -> Vec<Record> {
+            let mut ctx = Ctx::new();
+            ctx.add_attr_type(
+                "s",
+                Attr::Str("".into()),
+                Some(ComponentRule::StrNormalized { lowercase, trim }),
+            );
+            ["Hello", "hello", "HELLO ", "world"]
+                .into_iter()
+                .map(|raw_attr| Record::new(&ctx, vec![("s", raw_attr)]).unwrap())
+                .collect()
+        };
+
+        let lowercased = records(true, false);
+        assert_eq!(lowercased[0].group_id, lowercased[1].group_id);
+        assert_ne!(lowercased[0].group_id, lowercased[2].group_id);
+        assert_eq!(
+            lowercased[0].group_key,
+            vec![("s".into(), Attr::Str("hello".into()))]
+        );
+        assert_eq!(lowercased[0].attrs["s"], Attr::Str("Hello".into()));
+        assert_eq!(
+            Collection::new(lowercased.iter().collect()).group_count(),
+            3
+        );
+
+        let normalized = records(true, true);
+        assert_eq!(
+            Collection::new(normalized.iter().collect()).group_count(),
+            2
+        );
+
+        let raw = records(false, false);
+        assert_eq!(Collection::new(raw.iter().collect()).group_count(), 4);
+    }
+
+    #[test]
+    fn fold_first_and_last() {
+        let headers = ["userid", "time", "i"];
+        let mut ctx = Ctx::new();
+        ctx.add_attr_type("userid", Attr::Int(0), Some(ComponentRule::Unique));
+        ctx.add_attr_type("time", Attr::Float(0f32), None);
+        ctx.add_attr_type("i", Attr::Int(0), None);
+        ctx.add_attr_type("f", Attr::Float(0f32), None);
+        ctx.set_nullable("time");
+        let records: Vec<_> = [
+            // group_a
+            vec!["0", "1.1", "1"],
+            vec!["0", "3.9", "2"],
+            vec!["0", "2.9", "3"],
+            // group_b
+            vec!["1", "4.1", "4"],
+            vec!["1", "6.9", "5"],
+            vec!["1", "NA", "6"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let collection = Collection::new(records.iter().collect());
+        let values = |op: FoldOperation| -> Vec<Attr> {
+            let mut values: Vec<Attr> = collection
+                .fold(op)
+                .unwrap()
+                .result
+                .values()
+                .cloned()
+                .collect();
+            values.sort();
+            values
+        };
+
+        let last = FoldOperation::LAST {
+            value: "i".into(),
+            order_by: "time".into(),
+        };
+        assert_eq!(last.to_string(), "LAST(i ORDER BY time)");
+        assert_eq!(values(last), vec![Attr::Int(2), Attr::Int(5)]);
+        let first = FoldOperation::FIRST {
+            value: "i".into(),
+            order_by: "time".into(),
+        };
+        assert_eq!(values(first), vec![Attr::Int(1), Attr::Int(4)]);
+        let missing = FoldOperation::FIRST {
+            value: "i".into(),
+            order_by: "f".into(),
+        };
+        assert_eq!(values(missing), vec![Attr::Null, Attr::Null]);
+    }
+
+    #[test]
+    fn fold_concat() {
+        let headers = ["userid", "time", "s"];
+        let ctx = make_a_ctx();
+        let records: Vec<_> = [
+            vec!["0", "1", "world"],
+            vec!["0", "1", "hello"],
+            vec!["0", "1", "again"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .chain([Record::new(&ctx, vec![("userid", "0"), ("time", "1")]).unwrap()])
+        .collect();
+        let collection = Collection::new(records.iter().collect());
+        let concat = FoldOperation::CONCAT {
+            attr_name: "s".into(),
+            sep: ", ".into(),
+        };
+        assert_eq!(concat.to_string(), "CONCAT(s)");
+        let result = collection.fold(concat).unwrap();
+        assert_eq!(
+            result.result.values().collect::<Vec<_>>(),
+            vec![&Attr::Str("again, hello, world".into())]
+        );
+
+        let missing = FoldOperation::CONCAT {
+            attr_name: "i".into(),
+            sep: ",".into(),
+        };
+        assert_eq!(
+            collection
+                .fold(missing)
+                .unwrap()
+                .result
+                .values()
+                .collect::<Vec<_>>(),
+            vec![&Attr::Null]
+        );
+    }
+
+    #[test]
+    fn read_fold_result() {
+        let headers = ["userid", "time", "i"];
+        let ctx = make_a_ctx();
+        let records: Vec<_> = [
+            vec!["0", "1", "2"],
+            vec!["0", "2", "3"],
+            vec!["1", "1", "7"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let collection = Collection::new(records.iter().collect());
+        let result = collection.fold(FoldOperation::SUM("i".into())).unwrap();
+        assert_eq!(result.operation().to_string(), "SUM(i)");
+        assert_eq!(result.get(records[0].group_id), Some(&Attr::Float(5f32)));
+        assert_eq!(result.get(records[2].group_id), Some(&Attr::Float(7f32)));
+        assert_eq!(result.get(records[0].group_id ^ records[2].group_id), None);
+
+        let mut sums: Vec<(u64, &Attr)> = result
+            .iter()
+            .map(|(group, attr)| (group.id(), attr))
+            .collect();
+        sums.sort_by_key(|(id, _)| *id);
+        let mut expected = vec![
+            (records[0].group_id, &Attr::Float(5f32)),
+            (records[2].group_id, &Attr::Float(7f32)),
+        ];
+        expected.sort_by_key(|(id, _)| *id);
+        assert_eq!(sums, expected);
+    }
+
+    #[test]
+    fn coerce_attributes() {
+        let attrs = [
+            Attr::Int(-3),
+            Attr::Int64(1 << 40),
+            Attr::Float(0.5),
+            Attr::Bool(true),
+            Attr::Str("x".into()),
+            Attr::DateTime(NaiveDateTime::default()),
+            Attr::Null,
+        ];
+        let as_f64: Vec<Option<f64>> = attrs.iter().map(Attr::as_f64).collect();
+        assert_eq!(
+            as_f64,
+            vec![
+                Some(-3.0),
+                Some((1u64 << 40) as f64),
+                Some(0.5),
+                None,
+                None,
+                None,
+                None
+            ]
+        );
+        let as_i64: Vec<Option<i64>> = attrs.iter().map(Attr::as_i64).collect();
+        assert_eq!(
+            as_i64,
+            vec![Some(-3), Some(1 << 40), None, None, None, None, None]
+        );
+        let as_bool: Vec<Option<bool>> = attrs.iter().map(Attr::as_bool).collect();
+        assert_eq!(
+            as_bool,
+            vec![None, None, None, Some(true), None, None, None]
+        );
+        let as_str: Vec<Option<&str>> = attrs.iter().map(Attr::as_str).collect();
+        assert_eq!(as_str, vec![None, None, None, None, Some("x"), None, None]);
+    }
+
+    #[test]
+    fn sum_without_drift() {
+        let headers = ["userid", "time", "i"];
+        let ctx = make_a_ctx();
+        let records: Vec<_> = (1..=10000)
+            .map(|x| {
+                let i = x.to_string();
+                Record::new(&ctx, zip(headers, ["0", "1", i.as_str()]).collect()).unwrap()
+            })
+            .collect();
+        let collection = Collection::new(records.iter().collect());
+        let result = collection.fold(FoldOperation::SUM("i".into())).unwrap();
+        assert_eq!(
+            result.result.values().collect::<Vec<_>>(),
+            vec![&Attr::Float(50005000f32)]
+        );
+        // accumulating in f32 drifts once the sum exceeds 2^24
+        assert_ne!((1..=10000).map(|x| x as f32).sum::<f32>(), 50005000f32);
+
+        let result = collection.fold(FoldOperation::AVG("i".into())).unwrap();
+        assert_eq!(
+            result.result.values().collect::<Vec<_>>(),
+            vec![&Attr::Float(5000.5)]
+        );
+    }
+
+    #[test]
+    fn non_finite_floats() {
+        let headers = ["userid", "time", "f"];
+        let records = |ctx: &Ctx| -> Result<Vec<Record>, Error> {
+            [
+                vec!["0", "1", "1.5"],
+                vec!["0", "1", "inf"],
+                vec!["0", "1", "NaN"],
+            ]
+            .into_iter()
+            .map(|raw_record| Record::new(ctx, zip(headers, raw_record).collect()))
+            .collect()
+        };
+        let sum = |records: &[Record]| -> Attr {
+            let collection = Collection::new(records.iter().collect());
+            let result = collection.fold(FoldOperation::SUM("f".into())).unwrap();
+            result.result.values().next().unwrap().clone()
+        };
+
+        let mut ctx = make_a_ctx();
+        let propagated = records(&ctx).unwrap();
+        assert!(matches!(sum(&propagated), Attr::Float(v) if v.is_nan()));
+        let cmp = |ord: Ordering| FilterCond {
+            attr_name: "f".into(),
+            val: Attr::Float(0f32),
+            op: ord.into(),
+        };
+        let collection = Collection::new(propagated.iter().collect());
+        // NaN never matches, infinity does
+        assert_eq!(
+            collection
+                .clone()
+                .filter_records(cmp(Ordering::Greater))
+                .record_count(),
+            2
+        );
+        assert_eq!(
+            collection
+                .clone()
+                .filter_records(cmp(Ordering::Less))
+                .record_count(),
+            0
+        );
+        let nan = Predicate::Cmp {
+            attr_name: "f".into(),
+            val: Attr::Float(f32::NAN),
+            op: CmpOp::Eq,
+        };
+        assert!(collection.filter_records(nan).is_empty());
+
+        ctx.set_non_finite(NonFinite::Skip);
+        let skipped = records(&ctx).unwrap();
+        assert_eq!(skipped[1].attrs["f"], Attr::Null);
+        assert_eq!(sum(&skipped), Attr::Float(1.5));
+
+        ctx.set_non_finite(NonFinite::Error);
+        assert!(matches!(
+            records(&ctx),
+            Err(Error::Parse(e)) if e == ParseError {
+                header: "f".into(),
+                raw_attr: "inf".into(),
+                expected: "finite float"
+            }
+        ));
+    }
+
+    #[test]
+    fn project_records() {
+        let headers = ["userid", "time", "i", "f", "b", "s"];
+        let ctx = make_a_ctx();
+        let records: Vec<_> = [
+            vec!["23", "2", "0", ".23", "true", "hello"],
+            vec!["23", "3", "8", ".45", "true", "world"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+
+        let projected = records[0].project(&["i", "userid", "missing"]);
+        let projected: Vec<(&str, &Attr)> = projected
+            .iter()
+            .map(|(name, attr)| (name.as_str(), *attr))
+            .collect();
+        assert_eq!(
+            projected,
+            vec![("i", &Attr::Int(0)), ("userid", &Attr::Int(23))]
+        );
+
+        let collection = Collection::new(records.iter().collect());
+        let projected = collection.project(&["userid", "i"]);
+        assert_eq!(projected.len(), 2);
+        assert_eq!(projected[0]["i"], &Attr::Int(0));
+        assert_eq!(projected[1]["i"], &Attr::Int(8));
+        assert!(projected
+            .iter()
+            .all(|x| x.len() == 2 && x["userid"] == &Attr::Int(23)));
+    }
+
+    #[test]
+    fn flatten_records() {
+        let headers = ["userid", "time", "i"];
+        let ctx = make_a_ctx();
+        let records: Vec<_> = [
+            vec!["0", "1", "233"],
+            vec!["0", "1", "23"],
+            vec!["0", "5", "2333"],
+            vec!["1", "1", "0"],
+            vec!["2", "1", "-28"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let collection = Collection::new(records.iter().collect())
+            .filter_with(|record| record.attrs["i"] != Attr::Int(0));
+        let flattened = collection.records();
+        assert_eq!(flattened.len(), 4);
+        assert_eq!(
+            flattened.len(),
+            collection
+                .groups()
+                .map(|group| group.records().count())
+                .sum::<usize>()
+        );
+        // the order is deterministic
+        assert!(zip(&flattened, collection.records()).all(|(x, y)| std::ptr::eq(*x, y)));
+        assert_eq!(
+            Collection::new(flattened).group_count(),
+            collection.group_count()
+        );
+    }
+
+    #[test]
+    fn custom_bool_literals() {
+        let mut ctx = make_a_ctx();
+        ctx.add_attr_type("flag", Attr::Bool(false), None);
+        ctx.set_bool_literals("flag", &["yes", "Y", "1"], &["no", "N", "0"]);
+        let parse = |b: &str, flag: &str| {
+            Record::new(
+                &ctx,
+                zip(["userid", "time", "b", "flag"], ["0", "1", b, flag]).collect(),
+            )
+        };
+
+        let record = parse("true", "yes").unwrap();
+        assert_eq!(record.attrs["flag"], Attr::Bool(true));
+        assert_eq!(record.attrs["b"], Attr::Bool(true));
+        assert_eq!(parse("F", "no").unwrap().attrs["flag"], Attr::Bool(false));
+        assert_eq!(parse("t", "0").unwrap().attrs["flag"], Attr::Bool(false));
+        // the defaults are replaced for the column only
+        assert!(matches!(parse("true", "true"), Err(Error::Parse(e)) if e.header == "flag"));
+        assert!(matches!(parse("yes", "Y"), Err(Error::Parse(e)) if e.header == "b"));
+    }
+
+    #[cfg(feature = "decimal")]
+    #[test]
+    fn fold_decimals_exactly() {
+        let mut ctx = Ctx::new();
+        ctx.add_attr_type("userid", Attr::Int(0), Some(ComponentRule::Unique));
+        ctx.add_attr_type("price", Attr::Decimal(Decimal::ZERO), None);
+        ctx.add_attr_type("f", Attr::Float(0f32), None);
+        let records: Vec<_> = ["0.10", "0.20", "0.70", "19.99", "0.01"]
+            .into_iter()
+            .map(|raw_attr| {
+                Record::new(
+                    &ctx,
+                    vec![("userid", "0"), ("price", raw_attr), ("f", raw_attr)],
+                )
+                .unwrap()
+            })
+            .collect();
+        let collection = Collection::new(records.iter().collect());
+        let fold = |op: FoldOperation| {
+            collection
+                .fold(op)
+                .unwrap()
+                .result
+                .into_values()
+                .next()
+                .unwrap()
+        };
+
+        assert_eq!(
+            fold(FoldOperation::SUM("price".into())),
+            Attr::Decimal("21.00".parse().unwrap())
+        );
+        assert_eq!(
+            fold(FoldOperation::SUM("price".into())).to_string(),
+            "21.00"
+        );
+        assert_eq!(
+            fold(FoldOperation::AVG("price".into())),
+            Attr::Decimal("4.2".parse().unwrap())
+        );
+        // f32 can't represent the cents exactly
+        let cents = [0.1f32, 0.2, 0.7, 19.99, 0.01];
+        assert_ne!(cents.iter().map(|&x| x as f64).sum::<f64>(), 21f64);
+
+        assert!(Record::new(&ctx, vec![("userid", "0"), ("price", "1.2.3")]).is_err());
+        // decimals are grouped by value
+        let mut ctx = Ctx::new();
+        ctx.add_attr_type(
+            "price",
+            Attr::Decimal(Decimal::ZERO),
+            Some(ComponentRule::Unique),
+        );
+        let x = Record::new(&ctx, vec![("price", "1.0")]).unwrap();
+        let y = Record::new(&ctx, vec![("price", "1.00")]).unwrap();
+        assert_eq!(x.group_id, y.group_id);
+    }
+
+    #[test]
+    fn rolling_window() {
+        let headers = ["userid", "time", "i"];
+        let mut ctx = Ctx::new();
+        ctx.add_attr_type("userid", Attr::Int(0), Some(ComponentRule::Unique));
+        ctx.add_attr_type("time", Attr::Int(0), None);
+        ctx.add_attr_type("i", Attr::Int(0), None);
+        let records: Vec<Record> = [
+            vec!["0", "1", "1"],
+            vec!["0", "2", "2"],
+            vec!["0", "3", "3"],
+            vec!["0", "5", "4"],
+            vec!["0", "6", "5"],
+            vec!["1", "1", "10"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let collection = Collection::new(records.iter().collect());
+        let rolled = |window: Interval, op: FoldOperation| -> Vec<Vec<(Attr, Attr)>> {
+            let mut groups: Vec<Vec<(Attr, Attr)>> = collection
+                .rolling("time", window, op)
+                .unwrap()
+                .into_iter()
+                .map(|(_, rolled)| {
+                    rolled
+                        .into_iter()
+                        .map(|(record, attr)| (record.get("time").unwrap().clone(), attr))
+                        .collect()
+                })
+                .collect();
+            groups.sort_by_key(|rolled| rolled.len());
+            groups
+        };
+
+        // trailing window of 3, the first records only have partial windows
+        assert_eq!(
+            rolled(Interval::new(-2, 3), FoldOperation::SUM("i".into())),
+            vec![
+                vec![(Attr::Int(1), Attr::Float(10.0))],
+                vec![
+                    (Attr::Int(1), Attr::Float(1.0)),
+                    (Attr::Int(2), Attr::Float(3.0)),
+                    (Attr::Int(3), Attr::Float(6.0)),
+                    (Attr::Int(5), Attr::Float(7.0)),
+                    (Attr::Int(6), Attr::Float(9.0)),
+                ],
+            ]
+        );
+        // centered window
+        assert_eq!(
+            rolled(Interval::new(-1, 3), FoldOperation::COUNT)[1]
+                .iter()
+                .map(|(_, count)| count.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                Attr::Int(2),
+                Attr::Int(3),
+                Attr::Int(2),
+                Attr::Int(2),
+                Attr::Int(2)
+            ]
+        );
+    }
+
+    #[test]
+    fn pivot_table() {
+        let headers = ["userid", "time", "s", "i"];
+        let ctx = make_a_ctx();
+        let records: Vec<Record> = [
+            vec!["0", "1", "a", "1"],
+            vec!["0", "1", "b", "2"],
+            vec!["1", "1", "a", "3"],
+            vec!["1", "1", "a", "4"],
+            vec!["2", "4", "b", "5"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let collection = Collection::new(records.iter().collect());
+        let pivot = collection
+            .pivot("time", "s", "i", FoldOperation::SUM("i".into()))
+            .unwrap();
+        assert_eq!(pivot.index(), [Attr::Float(1.0), Attr::Float(4.0)]);
+        assert_eq!(
+            pivot.columns(),
+            [Attr::Str("a".into()), Attr::Str("b".into())]
+        );
+        assert_eq!(
+            pivot.rows().collect::<Vec<_>>(),
+            vec![
+                (&Attr::Float(1.0), &[Attr::Float(8.0), Attr::Float(2.0)][..]),
+                (&Attr::Float(4.0), &[Attr::Null, Attr::Float(5.0)][..]),
+            ]
+        );
+        assert_eq!(
+            pivot.get(&Attr::Float(4.0), &Attr::Str("a".into())),
+            Some(&Attr::Null)
+        );
+        assert_eq!(pivot.get(&Attr::Float(2.0), &Attr::Str("a".into())), None);
+        assert!(matches!(
+            collection.pivot("time", "i", "s", FoldOperation::AVG("s".into())),
+            Err(Error::Fold(_))
+        ));
+        assert!(matches!(
+            collection.pivot("time", "s", "userid", FoldOperation::SUM("i".into())),
+            Err(Error::PivotValue { .. })
+        ));
+        let counts = collection
+            .pivot("time", "s", "i", FoldOperation::COUNT)
+            .unwrap();
+        assert_eq!(
+            counts.get(&Attr::Float(1.0), &Attr::Str("a".into())),
+            Some(&Attr::Int(3))
+        );
+    }
 
-    fn make_a_ctx() -> Ctx {
+    #[test]
+    fn groups_sorted_by_key() {
+        let headers = ["userid", "time", "i"];
+        let raw_records = [
+            vec!["2", "1", "1"],
+            vec!["0", "4", "2"],
+            vec!["0", "1", "3"],
+            vec!["1", "7", "4"],
+            vec!["0", "1", "5"],
+        ];
+        let make_records = || -> Vec<Record> {
+            let ctx = make_a_ctx();
+            raw_records
+                .iter()
+                .map(|raw_record| {
+                    Record::new(&ctx, zip(headers, raw_record.clone()).collect()).unwrap()
+                })
+                .collect()
+        };
+        let (records, other_records) = (make_records(), make_records());
+        let collection = Collection::new(records.iter().collect());
+        let other = Collection::new(other_records.iter().rev().collect());
+        let keys = |collection: &Collection| -> Vec<Vec<(String, Attr)>> {
+            collection
+                .groups_sorted_by_key()
+                .into_iter()
+                .map(|group| group.key().to_vec())
+                .collect()
+        };
+        assert_eq!(keys(&collection), keys(&other));
+        assert_eq!(
+            collection
+                .groups_sorted_by_key()
+                .iter()
+                .map(|group| group.records().count())
+                .collect::<Vec<_>>(),
+            vec![2, 1, 1, 1]
+        );
+
+        let by_size = collection.groups_sorted_by(|group| group.records().count());
+        assert_eq!(by_size.len(), 4);
+        assert_eq!(by_size[3].records().count(), 2);
+    }
+
+    #[test]
+    fn dedup_by_value() {
+        let headers = ["userid", "time", "i"];
+        let ctx = make_a_ctx();
+        let records: Vec<Record> = [
+            vec!["0", "1", "233"],
+            vec!["0", "1", "23"],
+            vec!["0", "1", "233"],
+            vec!["1", "1", "233"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        assert!(records[0] == records[2]);
+        assert!(records[0] != records[3]);
+
+        let collection = Collection::new(records.iter().collect());
+        assert_eq!(collection.record_count(), 4);
+        let deduped = collection.dedup_by_value();
+        assert_eq!(deduped.group_count(), 2);
+        assert_eq!(deduped.record_count(), 3);
+        let mut values: Vec<&Attr> = deduped
+            .records()
+            .into_iter()
+            .filter_map(|record| record.get("i"))
+            .collect();
+        values.sort();
+        assert_eq!(values, [&Attr::Int(23), &Attr::Int(233), &Attr::Int(233)]);
+    }
+
+    #[test]
+    fn regroup_collection() {
+        let headers = ["userid", "time", "i"];
+        let ctx = make_a_ctx();
+        let records: Vec<Record> = [
+            vec!["0", "1", "1"],
+            vec!["0", "5", "2"],
+            vec!["1", "2", "3"],
+            vec!["2", "5", "4"],
+            vec!["2", "6", "5"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let collection = Collection::new(records.iter().collect());
+        assert_eq!(collection.group_count(), 4);
+
+        let mut by_userid = Ctx::new();
+        by_userid.add_attr_type("userid", Attr::Int(0), Some(ComponentRule::Unique));
+        let regrouped = collection.regroup(&by_userid).unwrap();
+        assert_eq!(regrouped.group_count(), 3);
+        assert_eq!(regrouped.record_count(), 5);
+
+        let mut by_time = Ctx::new();
+        by_time.add_attr_type(
+            "time",
+            Attr::Float(0.0),
+            Some(ComponentRule::Interval(Interval::new(1, 3))),
+        );
+        let regrouped = collection.regroup(&by_time).unwrap();
+        assert_eq!(regrouped.group_count(), 2);
+        assert_eq!(
+            regrouped.fold(FoldOperation::COUNT).unwrap().result.len(),
+            2
+        );
+
+        let mut by_missing = Ctx::new();
+        by_missing.add_attr_type("s", Attr::Str(String::new()), Some(ComponentRule::Unique));
+        assert!(matches!(
+            collection.regroup(&by_missing),
+            Err(Error::MissingGroupKey(_))
+        ));
+    }
+
+    #[test]
+    fn percentile_fold() {
+        let headers = ["userid", "time", "i"];
+        let ctx = make_a_ctx();
+        let records: Vec<_> = [
+            vec!["0", "1", "233"],
+            vec!["0", "1", "23"],
+            vec!["0", "1", "2333"],
+            vec!["0", "1", "0"],
+            vec!["0", "1", "-28"],
+            vec!["0", "1", "233"],
+            vec!["0", "1", "366"],
+            vec!["0", "1", "243"],
+            // a group without any value of `i`
+            vec!["1", "1"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let collection = Collection::new(records.iter().collect());
+        let percentile = |p: f64| -> Vec<Attr> {
+            let op = FoldOperation::PERCENTILE {
+                attr_name: "i".into(),
+                p,
+            };
+            let mut results: Vec<Attr> =
+                collection.fold(op).unwrap().result.into_values().collect();
+            results.sort();
+            results
+        };
+
+        assert_eq!(percentile(0.25), [Attr::Float(17.25), Attr::Null]);
+        assert_eq!(percentile(0.5), [Attr::Float(233.0), Attr::Null]);
+        assert_eq!(percentile(0.75), [Attr::Float(273.75), Attr::Null]);
+        assert_eq!(percentile(0.0), [Attr::Float(-28.0), Attr::Null]);
+        assert_eq!(percentile(1.0), [Attr::Float(2333.0), Attr::Null]);
+        assert_eq!(percentile(1.5), [Attr::Null, Attr::Null]);
+        assert_eq!(
+            FoldOperation::PERCENTILE {
+                attr_name: "i".into(),
+                p: 0.25
+            }
+            .to_string(),
+            "PERCENTILE(i, 0.25)"
+        );
+    }
+
+    #[test]
+    fn mode_fold() {
+        let headers = ["userid", "time", "i", "b", "s"];
+        let ctx = make_a_ctx();
+        let records: Vec<_> = [
+            vec!["0", "1", "3", "true", "a"],
+            vec!["0", "1", "7", "false", "b"],
+            vec!["0", "1", "3", "true", "b"],
+            vec!["0", "1", "3", "false", "c"],
+            vec!["0", "1", "5", "true", "c"],
+            // no value of `i`, `b` or `s`
+            vec!["1", "1"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let collection = Collection::new(records.iter().collect());
+        let mode = |attr_name: &str| -> Vec<Attr> {
+            let op = FoldOperation::MODE(attr_name.into());
+            let mut results: Vec<Attr> =
+                collection.fold(op).unwrap().result.into_values().collect();
+            results.sort();
+            results
+        };
+
+        assert_eq!(mode("i"), [Attr::Int(3), Attr::Null]);
+        assert_eq!(mode("b"), [Attr::Bool(true), Attr::Null]);
+        // `b` and `c` are equally frequent
+        assert_eq!(mode("s"), [Attr::Str("b".into()), Attr::Null]);
+        assert_eq!(FoldOperation::MODE("s".into()).to_string(), "MODE(s)");
+    }
+
+    #[test]
+    fn anti_join_blocklist() {
         let mut ctx = Ctx::new();
-        ctx.add_attr_type("userid", Attr::Int(0), Some(ComponentRule::Unique));
+        ctx.add_attr_type("userid", Attr::Int(0), None);
         ctx.add_attr_type(
-            "time",
+            "event",
+            Attr::Str(String::new()),
+            Some(ComponentRule::Unique),
+        );
+        ctx.set_nullable("userid");
+        let events: Vec<Record> = [
+            ("1", "login"),
+            ("2", "login"),
+            ("3", "buy"),
+            ("2", "buy"),
+            ("", "login"),
+        ]
+        .into_iter()
+        .map(|(userid, event)| {
+            Record::new(&ctx, vec![("userid", userid), ("event", event)]).unwrap()
+        })
+        .collect();
+        let blocked: Vec<Record> = ["2", "4", ""]
+            .into_iter()
+            .map(|userid| Record::new(&ctx, vec![("userid", userid), ("event", "block")]).unwrap())
+            .collect();
+        let events = Collection::new(events.iter().collect());
+        let blocked = Collection::new(blocked.iter().collect());
+
+        let allowed = events.anti_join(&blocked, "userid");
+        assert_eq!(allowed.group_count(), 2);
+        let mut userids: Vec<&Attr> = allowed
+            .records()
+            .into_iter()
+            .filter_map(|record| record.get("userid"))
+            .collect();
+        userids.sort();
+        assert_eq!(userids, [&Attr::Int(1), &Attr::Int(3), &Attr::Null]);
+        assert_eq!(events.anti_join(&events, "userid").record_count(), 1);
+        assert_eq!(events.anti_join(&blocked, "x").record_count(), 5);
+    }
+
+    #[test]
+    fn labeled_fold_result() {
+        let headers = ["userid", "time", "i"];
+        let ctx = make_a_ctx();
+        let records: Vec<Record> = [
+            vec!["0", "1", "1"],
+            vec!["0", "2", "2"],
+            vec!["1", "5", "3"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let collection = Collection::new(records.iter().collect());
+        let result = collection.fold(FoldOperation::SUM("i".into())).unwrap();
+        let mut labeled = result.labeled();
+        assert_eq!(labeled.len(), 2);
+        labeled.sort();
+        assert_eq!(
+            labeled[0],
+            (
+                vec![
+                    ("time".to_string(), Attr::Float(1.0)),
+                    ("userid".to_string(), Attr::Int(0)),
+                ],
+                Attr::Float(3.0)
+            )
+        );
+        assert_eq!(
+            labeled[1].0,
+            [
+                ("time".to_string(), Attr::Float(4.0)),
+                ("userid".to_string(), Attr::Int(1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn infer_with_overrides() {
+        let headers = ["zip", "i"];
+        let sample_rows = vec![vec!["01234", "1"], vec!["98765", "2"], vec!["", "3"]];
+        let overrides = HashMap::from([("zip", Attr::Str(String::new()))]);
+        let ctx = Ctx::infer_with_overrides(&headers, &sample_rows, &overrides);
+        assert_eq!(ctx.attr_type["zip"], Attr::Str(String::new()));
+        assert_eq!(ctx.attr_type["i"], Attr::Int(0));
+        let record = Record::new(&ctx, zip(headers, ["01234", "1"]).collect()).unwrap();
+        assert_eq!(record.attrs["zip"], Attr::Str("01234".into()));
+
+        let overrides = HashMap::from([("i", Attr::Float(0f32))]);
+        let ctx = Ctx::infer_with_overrides(&headers, &sample_rows, &overrides);
+        assert_eq!(ctx.attr_type["i"], Attr::Float(0f32));
+    }
+
+    #[test]
+    fn infer_leading_zeros() {
+        let headers = ["code", "zero", "f", "n"];
+        let sample_rows = vec![
+            vec!["001", "0", "0.5", "-01"],
+            vec!["002", "0", "-0.25", "1"],
+            vec!["010", "0", "10", "2"],
+        ];
+        let ctx = Ctx::infer(&headers, &sample_rows);
+        assert_eq!(ctx.attr_type["code"], Attr::Str(String::new()));
+        assert_eq!(ctx.attr_type["zero"], Attr::Int(0));
+        assert_eq!(ctx.attr_type["f"], Attr::Float(0f32));
+        assert_eq!(ctx.attr_type["n"], Attr::Str(String::new()));
+        let record = Record::new(&ctx, zip(headers, sample_rows[0].clone()).collect()).unwrap();
+        assert_eq!(record.attrs["code"], Attr::Str("001".into()));
+    }
+
+    #[test]
+    fn concat_collections() {
+        let headers = ["userid", "time", "i"];
+        let ctx = make_a_ctx();
+        let parse = |raw_records: Vec<Vec<&str>>| -> Vec<Record> {
+            raw_records
+                .into_iter()
+                .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+                .collect()
+        };
+        let monday = parse(vec![vec!["0", "1", "1"], vec!["1", "1", "2"]]);
+        let tuesday = parse(vec![
+            vec!["0", "2", "1"],
+            vec!["2", "1", "3"],
+            vec!["2", "5", "4"],
+        ]);
+        let collections = [
+            Collection::new(monday.iter().collect()),
+            Collection::new(tuesday.iter().collect()),
+        ];
+        let week = Collection::concat(&collections).unwrap();
+        assert_eq!(week.record_count(), 5);
+        assert_eq!(week.group_count(), 4);
+        // a record in both collections is kept once
+        let twice = Collection::concat(&[
+            Collection::new(monday.iter().collect()),
+            Collection::new(monday.iter().collect()),
+        ])
+        .unwrap();
+        assert_eq!(twice.record_count(), 2);
+        assert!(Collection::concat(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn sample_collection() {
+        let headers = ["userid", "time", "i"];
+        let ctx = make_a_ctx();
+        let records: Vec<Record> = (0..1000)
+            .map(|i| {
+                let (userid, i) = ((i % 2).to_string(), i.to_string());
+                Record::new(
+                    &ctx,
+                    zip(headers, [userid.as_str(), "1", i.as_str()]).collect(),
+                )
+                .unwrap()
+            })
+            .collect();
+        let collection = Collection::new(records.iter().collect());
+        let sample = |seed: u64| -> Vec<&Record> { collection.clone().sample(0.2, seed).records() };
+
+        let sampled = sample(7);
+        assert!((150..250).contains(&sampled.len()), "{}", sampled.len());
+        assert!(zip(&sampled, &sample(7)).all(|(x, y)| std::ptr::eq(*x, *y)));
+        assert_eq!(sample(7).len(), sampled.len());
+        assert!(!zip(&sampled, &sample(8)).all(|(x, y)| std::ptr::eq(*x, *y)));
+
+        assert!(collection.clone().sample(0.0, 7).is_empty());
+        assert_eq!(collection.sample(1.0, 7).record_count(), 1000);
+    }
+
+    #[test]
+    fn group_by_bins() {
+        let mut ctx = Ctx::new();
+        ctx.add_attr_type(
+            "age",
+            Attr::Int(0),
+            Some(ComponentRule::Bins(vec![0, 18, 65, i32::MAX])),
+        );
+        ctx.add_attr_type(
+            "height",
             Attr::Float(0f32),
-            Some(ComponentRule::Interval(Interval { start: 1, step: 3 })),
+            Some(ComponentRule::Bins(vec![100, 150, 200])),
         );
-        ctx.add_attr_type("i", Attr::Int(0), None);
-        ctx.add_attr_type("f", Attr::Float(0f32), None);
-        ctx.add_attr_type("b", Attr::Bool(false), None);
-        ctx.add_attr_type("s", Attr::Str("hello".into()), None);
-        ctx
+        let group_key = |age: &str, height: &str| -> Vec<(String, Attr)> {
+            Record::new(&ctx, vec![("age", age), ("height", height)])
+                .unwrap()
+                .group_key
+        };
+        let brackets = |age: &str| group_key(age, "170")[0].1.clone();
+        assert_eq!(brackets("0"), Attr::Int(0));
+        assert_eq!(brackets("17"), Attr::Int(0));
+        assert_eq!(brackets("18"), Attr::Int(1));
+        assert_eq!(brackets("64"), Attr::Int(1));
+        assert_eq!(brackets("65"), Attr::Int(2));
+        assert_eq!(brackets("101"), Attr::Int(2));
+        assert_eq!(brackets("-1"), Attr::Int(-1));
+
+        assert_eq!(group_key("30", "149.9")[1].1, Attr::Int(0));
+        assert_eq!(group_key("30", "150")[1].1, Attr::Int(1));
+        assert_eq!(group_key("30", "200")[1].1, Attr::Int(-1));
+        assert_eq!(group_key("30", "99.5")[1].1, Attr::Int(-1));
+    }
+
+    #[test]
+    fn record_from_typed() {
+        let mut ctx = make_a_ctx();
+        ctx.set_nullable("f");
+        let typed = Record::from_typed(
+            &ctx,
+            vec![
+                ("userid", Attr::Int(7)),
+                ("time", Attr::Float(2.5)),
+                ("f", Attr::Null),
+            ],
+        )
+        .unwrap();
+        let parsed = Record::new(&ctx, vec![("userid", "7"), ("time", "2.5"), ("f", "")]).unwrap();
+        assert!(typed == parsed);
+        assert_eq!(typed.group_id(), parsed.group_id());
+        assert_eq!(typed.get("time"), Some(&Attr::Float(2.5)));
+
+        let err = Record::from_typed(
+            &ctx,
+            vec![("userid", Attr::Float(7.0)), ("time", Attr::Float(2.5))],
+        )
+        .err()
+        .unwrap();
+        assert!(matches!(
+            err,
+            Error::Parse(ParseError {
+                expected: "int",
+                ..
+            })
+        ));
+        assert!(matches!(
+            Record::from_typed(&ctx, vec![("userid", Attr::Int(7)), ("i", Attr::Null)]),
+            Err(Error::Parse(_))
+        ));
+        assert!(matches!(
+            Record::from_typed(&ctx, vec![("x", Attr::Int(7))]),
+            Err(Error::MissingHeader(_))
+        ));
+    }
+
+    #[test]
+    fn count_if_fold() {
+        let headers = ["userid", "time", "i", "s"];
+        let ctx = make_a_ctx();
+        let records: Vec<_> = [
+            vec!["0", "1", "233", "a"],
+            vec!["0", "1", "23", "b"],
+            vec!["0", "1", "2333", "c"],
+            vec!["1", "1", "23", "a"],
+            // no value of `i`
+            vec!["1", "1"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let collection = Collection::new(records.iter().collect());
+        let counts = |op: FoldOperation| -> Vec<(Attr, Attr)> {
+            let result = collection.fold(op).unwrap();
+            let mut counts: Vec<(Attr, Attr)> = result
+                .labeled()
+                .into_iter()
+                .map(|(key, count)| {
+                    let (_, userid) = key.into_iter().find(|(name, _)| name == "userid").unwrap();
+                    (userid, count)
+                })
+                .collect();
+            counts.sort();
+            counts
+        };
+        let over_200 = Predicate::Cmp {
+            attr_name: "i".into(),
+            val: Attr::Int(200),
+            op: CmpOp::Gt,
+        };
+        let op = FoldOperation::COUNT_IF(over_200);
+        assert_eq!(op.to_string(), "COUNT_IF(i > 200)");
+        assert_eq!(
+            counts(op),
+            [(Attr::Int(0), Attr::Int(2)), (Attr::Int(1), Attr::Int(0))]
+        );
+
+        let a_or_not_b = Predicate::Or(
+            Box::new(Predicate::StartsWith {
+                attr_name: "s".into(),
+                pattern: "a".into(),
+                case_insensitive: false,
+            }),
+            Box::new(Predicate::Not(Box::new(Predicate::Contains {
+                attr_name: "s".into(),
+                pattern: "B".into(),
+                case_insensitive: true,
+            }))),
+        );
+        assert_eq!(a_or_not_b.to_string(), "(s LIKE 'a%' OR NOT s ILIKE '%B%')");
+        // a missing `s` contains nothing, so `NOT` matches it
+        assert_eq!(
+            counts(FoldOperation::COUNT_IF(a_or_not_b)),
+            [(Attr::Int(0), Attr::Int(2)), (Attr::Int(1), Attr::Int(2))]
+        );
+    }
+
+    #[test]
+    fn distinct_values_per_group() {
+        let headers = ["userid", "time", "i"];
+        let ctx = make_a_ctx();
+        let records: Vec<_> = [
+            vec!["0", "1", "2333"],
+            vec!["0", "1", "23"],
+            vec!["0", "1", "2333"],
+            vec!["0", "1", "233"],
+            vec!["0", "1"],
+            vec!["1", "1"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let collection = Collection::new(records.iter().collect());
+        let distinct = collection.distinct_values("i");
+        assert_eq!(
+            distinct[&records[0].group_id()],
+            [Attr::Int(23), Attr::Int(233), Attr::Int(2333)]
+        );
+        assert!(distinct[&records[5].group_id()].is_empty());
+        assert_eq!(distinct.len(), 2);
+    }
+
+    #[test]
+    fn describe_groups() {
+        let headers = ["userid", "time", "i", "s"];
+        let ctx = make_a_ctx();
+        let records: Vec<_> = [
+            vec!["0", "1", "3", "a"],
+            vec!["0", "1", "1", "b"],
+            vec!["0", "1", "4", "c"],
+            vec!["0", "1", "2", "d"],
+            vec!["1", "1", "7", "e"],
+            vec!["1", "1"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let collection = Collection::new(records.iter().collect());
+        let described = collection.describe("i").unwrap();
+        assert_eq!(
+            described[&records[0].group_id()],
+            Describe {
+                count: 4,
+                mean: Some(2.5),
+                stddev: Some((5.0f64 / 3.0).sqrt()),
+                min: Some(1.0),
+                p25: Some(1.75),
+                p50: Some(2.5),
+                p75: Some(3.25),
+                max: Some(4.0),
+            }
+        );
+        let single = &described[&records[4].group_id()];
+        assert_eq!(
+            (single.count, single.p50, single.stddev),
+            (1, Some(7.0), None)
+        );
+
+        let err = collection.describe("s").err().unwrap();
+        assert_eq!(err.op, "DESCRIBE");
+        assert_eq!(err.attr_name, "s");
+    }
+
+    #[test]
+    fn record_value_equality() {
+        let headers = ["userid", "time", "i", "f", "s"];
+        let ctx = make_a_ctx();
+        let make =
+            |raw_record: Vec<&str>| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap();
+        let x = make(vec!["0", "1", "233", "2.5", "a"]);
+        assert!(x == make(vec!["0", "1", "233", "2.5", "a"]));
+        assert!(x != make(vec!["0", "1", "233", "2.50001", "a"]));
+        assert!(x != make(vec!["0", "1", "233", "2.5", "b"]));
+        // records with fewer attributes differ
+        assert!(x != make(vec!["0", "1", "233", "2.5"]));
+        // floats compare by total ordering, so signed zeros differ
+        assert!(make(vec!["0", "1", "233", "0"]) != make(vec!["0", "1", "233", "-0"]));
+    }
+
+    #[test]
+    fn owned_collection_outlives_records() {
+        fn load() -> OwnedCollection {
+            let headers = ["userid", "time", "i"];
+            let ctx = make_a_ctx();
+            let records: Vec<Record> = [
+                vec!["0", "1", "233"],
+                vec!["0", "1", "23"],
+                vec!["1", "1", "2333"],
+            ]
+            .into_iter()
+            .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+            .collect();
+            OwnedCollection::new(records)
+        }
+
+        let owned = load();
+        let collection = owned.collection();
+        assert_eq!(collection.group_count(), 2);
+        let mut sums: Vec<Attr> = collection
+            .fold(FoldOperation::SUM("i".into()))
+            .unwrap()
+            .result
+            .into_values()
+            .collect();
+        sums.sort();
+        assert_eq!(sums, [Attr::Float(256.0), Attr::Float(2333.0)]);
+
+        // a filtered collection is copied out of its borrowed records
+        let filtered = OwnedCollection::from(
+            &collection
+                .clone()
+                .filter_with(|record| record.get("i") > Some(&Attr::Int(200))),
+        );
+        drop(collection);
+        drop(owned);
+        let mut values: Vec<&Attr> = filtered
+            .records()
+            .iter()
+            .filter_map(|record| record.get("i"))
+            .collect();
+        values.sort();
+        assert_eq!(values, [&Attr::Int(233), &Attr::Int(2333)]);
+
+        // records are shared rather than copied
+        let shared = OwnedCollection::try_from_shared(filtered.records().to_vec()).unwrap();
+        assert!(Arc::ptr_eq(&shared.records()[1], &filtered.records()[1]));
+    }
+
+    #[test]
+    fn parse_batch_collects_bad_rows() {
+        let ctx = make_a_ctx();
+        let headers = ["userid", "time", "i"];
+        let rows = vec![
+            vec!["0", "1", "233"],
+            vec!["0", "1", "x"],
+            vec!["1", "4", "2333"],
+            // no `time`, which is grouped by
+            vec!["1"],
+        ];
+        let (records, errors) = Record::parse_batch(&ctx, &headers, &rows);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].get("i"), Some(&Attr::Int(2333)));
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(
+            &errors[0],
+            RowError { row: 1, error: Error::Parse(e) } if e.raw_attr == "x"
+        ));
+        assert!(matches!(errors[1].error, Error::MissingGroupKey(_)));
+        assert_eq!(errors[1].row, 3);
+        assert!(errors[0].to_string().starts_with("row 1: "));
+    }
+
+    #[test]
+    fn header_aliases() {
+        let mut ctx = make_a_ctx();
+        ctx.add_alias("userid", "user_id");
+        ctx.add_alias("userid", "userId");
+        let expected =
+            Record::new(&ctx, vec![("userid", "0"), ("time", "1"), ("i", "233")]).unwrap();
+        for header in ["user_id", "userId"] {
+            let record =
+                Record::new(&ctx, vec![(header, "0"), ("time", "1"), ("i", "233")]).unwrap();
+            assert_eq!(record.get("userid"), Some(&Attr::Int(0)));
+            assert_eq!(record.get(header), None);
+            assert!(record == expected);
+            assert_eq!(record.group_id(), expected.group_id());
+        }
+        let from_typed = Record::from_typed(
+            &ctx,
+            vec![
+                ("user_id", Attr::Int(0)),
+                ("time", Attr::Float(1.0)),
+                ("i", Attr::Int(233)),
+            ],
+        )
+        .unwrap();
+        assert!(from_typed == expected);
+
+        // an alias is parsed as the type of its attribute
+        let err = Record::new(&ctx, vec![("user_id", "x"), ("time", "1")])
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::Parse(e) if e.header == "user_id" && e.expected == "int"));
+        let err = Record::new(&ctx, vec![("uid", "0"), ("time", "1")])
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::MissingHeader(header) if header == "uid"));
+    }
+
+    #[test]
+    fn filter_not_equal_and_at_least() {
+        let headers = ["userid", "time", "i"];
+        let ctx = make_a_ctx();
+        let records: Vec<_> = [
+            vec!["0", "1", "23"],
+            vec!["0", "1", "233"],
+            vec!["1", "1", "2333"],
+            vec!["1", "1", "233"],
+            // missing `i` never matches, even `!=`
+            vec!["1", "1"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let filtered = |op: CmpOp| -> Vec<Attr> {
+            let mut values: Vec<Attr> = Collection::new(records.iter().collect())
+                .filter_records(Predicate::Cmp {
+                    attr_name: "i".into(),
+                    val: Attr::Int(233),
+                    op,
+                })
+                .records()
+                .into_iter()
+                .map(|record| record.attrs["i"].clone())
+                .collect();
+            values.sort();
+            values
+        };
+
+        assert_eq!(filtered(CmpOp::Ne), [Attr::Int(23), Attr::Int(2333)]);
+        assert_eq!(
+            filtered(CmpOp::Ge),
+            [Attr::Int(233), Attr::Int(233), Attr::Int(2333)]
+        );
+        assert_eq!(
+            filtered(CmpOp::Le),
+            [Attr::Int(23), Attr::Int(233), Attr::Int(233)]
+        );
+        assert_eq!(filtered(Ordering::Equal.into()), filtered(CmpOp::Eq));
+        assert_eq!(CmpOp::from(Ordering::Greater), CmpOp::Gt);
+        assert_eq!(CmpOp::Ge.to_string(), ">=");
+    }
+
+    #[test]
+    fn filter_in_set() {
+        let headers = ["userid", "time", "f", "s"];
+        let ctx = make_a_ctx();
+        let records: Vec<_> = [
+            vec!["3", "1", "0.5", "a"],
+            vec!["4", "1", "1.5", "b"],
+            vec!["7", "1", "2.5", "c"],
+            vec!["9", "1", "0.5", "d"],
+            vec!["9", "1"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let collection = Collection::new(records.iter().collect());
+        let matched = |attr_name: &str, values: Vec<Attr>| -> Vec<Attr> {
+            let predicate = Predicate::In {
+                attr_name: attr_name.into(),
+                values: values.into_iter().collect(),
+            };
+            let mut userids: Vec<Attr> = collection
+                .clone()
+                .filter_records(predicate)
+                .records()
+                .into_iter()
+                .map(|record| record.attrs["userid"].clone())
+                .collect();
+            userids.sort();
+            userids
+        };
+
+        assert_eq!(
+            matched("userid", [3, 7, 9].map(Attr::Int).to_vec()),
+            [3, 7, 9, 9].map(Attr::Int)
+        );
+        assert_eq!(matched("f", vec![Attr::Float(0.5)]), [3, 9].map(Attr::Int));
+        // records missing `s` never match
+        assert_eq!(
+            matched("s", vec![Attr::Str("d".into()), Attr::Str("x".into())]),
+            [Attr::Int(9)]
+        );
+        assert!(matched("userid", Vec::new()).is_empty());
+
+        let predicate = Predicate::In {
+            attr_name: "userid".into(),
+            values: [9, 3, 7].map(Attr::Int).into_iter().collect(),
+        };
+        assert_eq!(predicate.to_string(), "userid IN (3, 7, 9)");
+    }
+
+    #[test]
+    fn map_records() {
+        let headers = ["userid", "time", "i"];
+        let ctx = make_a_ctx();
+        let records: Vec<_> = [
+            vec!["0", "1", "233"],
+            vec!["0", "1", "23"],
+            vec!["1", "1", "2333"],
+            vec!["1", "1"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let collection = Collection::new(records.iter().collect());
+        let double = |record: &Record| {
+            let attrs = record
+                .attrs()
+                .map(|(name, attr)| match (name, attr) {
+                    ("i", Attr::Int(v)) => (name, Attr::Int(v * 2)),
+                    _ => (name, attr.clone()),
+                })
+                .collect();
+            Record::from_typed(&ctx, attrs)
+        };
+        let doubled = collection.map(double).unwrap();
+        let mut values: Vec<Attr> = doubled
+            .records()
+            .iter()
+            .filter_map(|record| record.get("i").cloned())
+            .collect();
+        values.sort();
+        assert_eq!(values, [46, 466, 4666].map(Attr::Int));
+        // `i` isn't grouped by, so groups are kept
+        assert_eq!(
+            doubled.collection().size_by_group(),
+            collection.size_by_group()
+        );
+
+        // changing a group-by attribute moves the record
+        let merged = collection
+            .map(|record| {
+                let attrs = record
+                    .attrs()
+                    .map(|(name, attr)| match name {
+                        "userid" => (name, Attr::Int(0)),
+                        _ => (name, attr.clone()),
+                    })
+                    .collect();
+                Record::from_typed(&ctx, attrs)
+            })
+            .unwrap();
+        assert_eq!(merged.collection().group_count(), 1);
+
+        let err = collection
+            .map(|_| Record::from_typed(&ctx, vec![("i", Attr::Int(0))]))
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::MissingGroupKey(_)));
     }
 
     #[test]
-    fn construct_record() {
-        let headers = vec!["userid", "time", "i", "f", "b", "s"];
-        let raw_record_a = vec!["23", "2", "0", ".23", "true", "hello"];
-        let raw_record_b = vec!["23", "3", "8", ".45", "true", "world"];
-        let raw_record_c = vec!["24", "2", "1", ".25", "false", "yeah"];
-        let ctx = make_a_ctx();
-
-        let record_a = Record::new(
-            &ctx,
-            zip(headers.iter(), raw_record_a.into_iter())
-                .map(|(x, y)| (x.to_owned(), y))
-                .collect(),
-        );
+    fn unknown_column_policies() {
+        let mut ctx = make_a_ctx();
+        let raw_record = vec![
+            ("userid", "0"),
+            ("time", "1"),
+            ("note", " hi "),
+            ("i", "233"),
+        ];
+        let err = Record::new(&ctx, raw_record.clone()).err().unwrap();
+        assert!(matches!(err, Error::MissingHeader(header) if header == "note"));
 
-        assert_eq!(record_a.attrs.get("userid"), Some(&Attr::Int(23)));
-        assert_eq!(record_a.attrs.get("time"), Some(&Attr::Float(2f32)));
-        assert_eq!(record_a.attrs.get("i"), Some(&Attr::Int(0)));
-        assert_eq!(record_a.attrs.get("f"), Some(&Attr::Float(0.23f32)));
-        assert_eq!(record_a.attrs.get("b"), Some(&Attr::Bool(true)));
-        assert_eq!(record_a.attrs.get("x"), None);
+        ctx.set_unknown_column_policy(UnknownColumn::AsString);
+        let record = Record::new(&ctx, raw_record.clone()).unwrap();
+        assert_eq!(record.get("note"), Some(&Attr::Str(" hi ".into())));
+        assert_eq!(record.get("i"), Some(&Attr::Int(233)));
+        ctx.set_trim_strings(true);
+        let record = Record::new(&ctx, raw_record.clone()).unwrap();
+        assert_eq!(record.get("note"), Some(&Attr::Str("hi".into())));
 
-        let record_b = Record::new(
-            &ctx,
-            zip(headers.iter(), raw_record_b.iter())
-                .map(|(x, y)| (x.to_owned(), y.to_owned()))
-                .collect(),
-        );
-        let record_c = Record::new(
-            &ctx,
-            zip(headers.iter(), raw_record_c.iter())
-                .map(|(x, y)| (x.to_owned(), y.to_owned()))
-                .collect(),
-        );
+        ctx.set_unknown_column_policy(UnknownColumn::Ignore);
+        let record = Record::new(&ctx, raw_record.clone()).unwrap();
+        assert_eq!(record.get("note"), None);
+        assert_eq!(record.attrs().count(), 3);
+        // declared columns are still parsed as their types
+        let err = Record::new(&ctx, vec![("userid", "x"), ("time", "1")])
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::Parse(_)));
 
-        assert_eq!(record_a.group_id, record_b.group_id);
-        assert_ne!(record_a.group_id, record_c.group_id);
+        ctx.set_unknown_column_policy(UnknownColumn::Error);
+        assert!(Record::new(&ctx, raw_record).is_err());
     }
 
     #[test]
-    fn group_record() {
-        let headers = vec!["userid", "time"];
+    fn rms_fold() {
+        let headers = ["userid", "time", "f", "s"];
         let ctx = make_a_ctx();
-        let record_group_a = vec![
-            vec!["0", "1.1"],
-            vec!["0", "2.9"],
-            vec!["0", "3"],
-            vec!["0", "3.9"],
-        ];
-        let record_group_b = vec![vec!["0", "7.9"], vec!["0", "9"]];
-        let record_group_c = vec![vec!["1", "4.1"], vec!["1", "6.9"]];
+        let records: Vec<_> = [
+            vec!["0", "1", "1", "a"],
+            vec!["0", "1", "-5", "b"],
+            vec!["0", "1", "7"],
+            // no value of `f`
+            vec!["1", "1"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let collection = Collection::new(records.iter().collect());
+        let op = FoldOperation::RMS("f".into());
+        assert_eq!(op.to_string(), "RMS(f)");
+        let result = collection.fold(op).unwrap();
+        // sqrt((1 + 25 + 49) / 3) = 5
+        assert_eq!(result.get(records[0].group_id()), Some(&Attr::Float(5.0)));
+        assert_eq!(result.get(records[3].group_id()), Some(&Attr::Null));
 
-        let get_group_state = |record_group: Vec<Vec<&str>>| -> (bool, Option<u64>) {
-            record_group
-                .into_iter()
-                .map(|raw_record| {
-                    Record::new(
-                        &ctx,
-                        zip(headers.iter(), raw_record.iter())
-                            .map(|(x, y)| (x.to_owned(), y.to_owned()))
-                            .collect(),
-                    )
-                })
-                .fold((true, None), |(is_same_group_id, group_id), x| {
-                    if let Some(group_id) = group_id {
-                        if is_same_group_id && x.group_id == group_id {
-                            (true, Some(x.group_id))
-                        } else {
-                            (false, Some(x.group_id))
-                        }
-                    } else {
-                        (true, Some(x.group_id))
-                    }
-                })
-        };
+        let err = collection
+            .fold(FoldOperation::RMS("s".into()))
+            .err()
+            .unwrap();
+        assert_eq!(err.op, "RMS");
+    }
 
-        let (is_same_group_id_a, group_id_a) = get_group_state(record_group_a);
-        let (is_same_group_id_b, group_id_b) = get_group_state(record_group_b);
-        let (is_same_group_id_c, group_id_c) = get_group_state(record_group_c);
-        assert_eq!(is_same_group_id_a, true);
-        assert_eq!(is_same_group_id_b, true);
-        assert_eq!(is_same_group_id_c, true);
-        assert_ne!(group_id_a, group_id_b);
-        assert_ne!(group_id_b, group_id_c);
-        assert_ne!(group_id_c, group_id_a);
+    #[test]
+    fn group_ids_ignore_declaration_order() {
+        let declare = |names: [&str; 3]| {
+            let mut ctx = Ctx::new();
+            for name in names {
+                match name {
+                    "userid" => ctx.add_attr_type(name, Attr::Int(0), Some(ComponentRule::Unique)),
+                    "time" => ctx.add_attr_type(
+                        name,
+                        Attr::Float(0.0),
+                        Some(ComponentRule::Interval(Interval::new(1, 3))),
+                    ),
+                    _ => ctx.add_attr_type(
+                        name,
+                        Attr::Str(String::new()),
+                        Some(ComponentRule::StrNormalized {
+                            lowercase: true,
+                            trim: true,
+                        }),
+                    ),
+                }
+            }
+            ctx
+        };
+        let raw_record = vec![("userid", "7"), ("time", "4.5"), ("s", " Ab ")];
+        let x = Record::new(&declare(["userid", "time", "s"]), raw_record.clone()).unwrap();
+        let y = Record::new(&declare(["s", "time", "userid"]), raw_record).unwrap();
+        assert_eq!(x.group_id(), y.group_id());
+        assert_eq!(x.group_key, y.group_key);
+        // keys are ordered by attribute name
+        let names: Vec<&str> = x.group_key.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["s", "time", "userid"]);
     }
 
     #[test]
-    fn construct_collection() {
-        let headers = vec!["userid", "time"];
+    fn validate_collection() {
+        let headers = ["userid", "time", "i"];
         let ctx = make_a_ctx();
-        let records = vec![
-            // group_a
-            vec!["0", "1.1"],
-            vec!["0", "2.9"],
-            vec!["0", "3"],
-            vec!["0", "3.9"],
-            // group_b
-            vec!["0", "7.9"],
-            vec!["0", "9"],
-            // group_c
-            vec!["1", "4.1"],
-            vec!["1", "6.9"],
-        ];
-        let records: Vec<_> = records
-            .into_iter()
-            .map(|raw_record| {
-                Record::new(
-                    &ctx,
-                    zip(headers.iter(), raw_record.into_iter())
-                        .map(|(x, y)| (x.to_owned(), y))
-                        .collect(),
-                )
-            })
-            .collect();
-        let collection = Collection::new(records.iter().collect());
-        assert_eq!(collection.groups.len(), 3);
+        let records: Vec<_> = [
+            vec!["0", "1", "233"],
+            vec!["0", "1", "23"],
+            vec!["1", "1", "2333"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let a = Collection::new(records[..2].iter().collect());
+        let b = Collection::new(records[1..].iter().collect());
+        for collection in [
+            a.clone().intersection(&b),
+            a.clone().difference(&b),
+            a.clone().symmetric_difference(&b),
+            a.clone().union(&b).difference(&a).difference(&b),
+        ] {
+            collection.validate().unwrap();
+        }
+
+        let id = records[0].group_id();
+        let empty = Collection {
+            groups: HashMap::from([(id, Group::new(id, records[0].group_key.clone()))]),
+        };
+        let err = empty.validate().err().unwrap();
+        assert!(matches!(err, Error::InvalidCollection(_)));
+        assert_eq!(
+            err.to_string(),
+            format!("Error: invalid collection, group `{}' has no record", id)
+        );
+        let mut misplaced = a.clone();
+        let group = misplaced.groups.remove(&id).unwrap();
+        misplaced.groups.insert(id + 1, group);
+        assert!(matches!(
+            misplaced.validate(),
+            Err(Error::InvalidCollection(_))
+        ));
     }
 
     #[test]
-    fn filter_collection() {
-        let headers = vec!["userid", "time", "i"];
+    fn streaming_fold_matches_batch() {
+        let headers = ["userid", "time", "i"];
         let ctx = make_a_ctx();
-        let records = vec![
+        let rows = [
             vec!["0", "1", "233"],
             vec!["0", "1", "23"],
             vec!["0", "1", "2333"],
-            vec!["0", "1", "0"],
-            vec!["0", "1", "-28"],
-            vec!["0", "1", "233"],
-            vec!["0", "1", "366"],
-            vec!["0", "1", "243"],
+            vec!["1", "1", "0"],
+            vec!["1", "1", "-28"],
+            vec!["1", "1"],
         ];
-        let records: Vec<_> = records
-            .into_iter()
+        let records: Vec<_> = rows
+            .iter()
             .map(|raw_record| {
-                Record::new(
-                    &ctx,
-                    zip(headers.iter(), raw_record.into_iter())
-                        .map(|(x, y)| (x.to_owned(), y))
-                        .collect(),
-                )
+                Record::new(&ctx, zip(headers, raw_record.clone()).collect()).unwrap()
             })
             .collect();
         let collection = Collection::new(records.iter().collect());
-        let filter_cond = FilterCond {
+        let over_200 = || Predicate::Cmp {
             attr_name: "i".into(),
-            val: Attr::Int(232),
-            ord: Ordering::Greater,
+            val: Attr::Int(200),
+            op: CmpOp::Gt,
         };
-        let collection = collection.filter_records(filter_cond);
+        let ops: [fn() -> FoldOperation; 4] = [
+            || FoldOperation::SUM("i".into()),
+            || FoldOperation::AVG("i".into()),
+            || FoldOperation::RMS("i".into()),
+            || FoldOperation::COUNT,
+        ];
+        for op in ops {
+            let mut streaming = StreamingFold::new(op()).unwrap();
+            // each record is dropped once it's pushed
+            for raw_record in &rows {
+                let record = Record::new(&ctx, zip(headers, raw_record.clone()).collect()).unwrap();
+                streaming.push(&record).unwrap();
+            }
+            let mut streamed: Vec<_> = streaming.finish().into_iter().collect();
+            streamed.sort();
+            let mut batch = collection.fold(op()).unwrap().labeled();
+            batch.sort();
+            assert_eq!(streamed, batch);
+        }
+        let mut streaming = StreamingFold::new(FoldOperation::COUNT_IF(over_200())).unwrap();
+        records
+            .iter()
+            .for_each(|record| streaming.push(record).unwrap());
+        assert_eq!(streaming.finish()[&records[0].group_key], Attr::Int(2));
 
-        assert_eq!(collection.groups.iter().next().unwrap().1.records.len(), 5);
+        assert!(StreamingFold::new(FoldOperation::MODE("i".into())).is_none());
+        let mut streaming = StreamingFold::new(FoldOperation::SUM("s".into())).unwrap();
+        let record = Record::new(&ctx, vec![("userid", "0"), ("time", "1"), ("s", "a")]).unwrap();
+        let err = streaming.push(&record).err().unwrap();
+        assert!(matches!(err, Error::Fold(e) if e.op == "SUM"));
     }
 
+    #[cfg(feature = "decimal")]
     #[test]
-    fn set_operations_on_collections() {
-        let headers = vec!["userid", "time", "i"];
-        let ctx = make_a_ctx();
-        let records = vec![
-            vec!["0", "1", "233"],
-            vec!["0", "1", "23"],
-            vec!["0", "1", "2333"],
-            vec!["0", "1", "0"],
-            vec!["0", "1", "-28"],
-            vec!["0", "1", "233"],
-            vec!["0", "1", "366"],
-            vec!["0", "1", "243"],
-        ];
-        let records: Vec<_> = records
+    fn streaming_fold_matches_batch_decimals() {
+        let mut ctx = Ctx::new();
+        ctx.add_attr_type("userid", Attr::Int(0), Some(ComponentRule::Unique));
+        ctx.add_attr_type("price", Attr::Decimal(Decimal::ZERO), None);
+        let records: Vec<_> = [("0", "0.10"), ("0", "0.20"), ("0", "19.99"), ("1", "0.01")]
             .into_iter()
-            .map(|raw_record| {
-                Record::new(
-                    &ctx,
-                    zip(headers.iter(), raw_record.into_iter())
-                        .map(|(x, y)| (x.to_owned(), y))
-                        .collect(),
-                )
+            .map(|(userid, price)| {
+                Record::new(&ctx, vec![("userid", userid), ("price", price)]).unwrap()
             })
             .collect();
-        let whole_view = records.iter().collect();
-        let end_with_3_view = records
+        let collection = Collection::new(records.iter().collect());
+        let ops: [fn() -> FoldOperation; 2] = [
+            || FoldOperation::SUM("price".into()),
+            || FoldOperation::AVG("price".into()),
+        ];
+        for op in ops {
+            let mut streaming = StreamingFold::new(op()).unwrap();
+            records
+                .iter()
+                .for_each(|record| streaming.push(record).unwrap());
+            let mut streamed: Vec<_> = streaming.finish().into_iter().collect();
+            streamed.sort();
+            let mut batch = collection.fold(op()).unwrap().labeled();
+            batch.sort();
+            assert_eq!(streamed, batch);
+        }
+        let mut streaming = StreamingFold::new(FoldOperation::SUM("price".into())).unwrap();
+        records
             .iter()
-            .filter(|record| {
-                if let Some(Attr::Int(x)) = record.attrs.get("i") {
-                    x % 10 == 3
-                } else {
-                    false
-                }
-            })
-            .collect();
-        let whole_view = Collection::new(whole_view);
-        let end_with_3_view = Collection::new(end_with_3_view);
-        let intersection = whole_view.intersection(&end_with_3_view);
+            .for_each(|record| streaming.push(record).unwrap());
+        assert_eq!(
+            streaming.finish()[&records[0].group_key],
+            Attr::Decimal("20.29".parse().unwrap())
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn collection_to_json() {
+        use serde_json::json;
 
+        let headers = ["userid", "time", "f", "s"];
+        let ctx = make_a_ctx();
+        let records: Vec<_> = [
+            vec!["0", "1", "0.1", "a"],
+            vec!["0", "1", "2", "b"],
+            vec!["0", "1", "inf"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let collection = Collection::new(records.iter().collect());
         assert_eq!(
-            intersection.groups.iter().next().unwrap().1.records.len(),
-            5
+            collection.to_json(),
+            json!([
+                {"userid": 0, "time": 1.0, "f": 0.1, "s": "a"},
+                {"userid": 0, "time": 1.0, "f": 2.0, "s": "b"},
+                {"userid": 0, "time": 1.0, "f": null},
+            ])
         );
+        let json = collection.to_json().to_string();
+        assert!(json.starts_with(r#"[{"userid":0,"time":1.0,"f":0.1,"s":"a"}"#));
 
-        let union = intersection.union(&end_with_3_view);
-        assert_eq!(union.groups.iter().next().unwrap().1.records.len(), 5);
+        let finite = collection.filter_with(|record| record.attrs.len() == 4);
+        let result = finite.fold(FoldOperation::SUM("f".into())).unwrap();
+        assert_eq!(
+            result.to_json(),
+            json!([{"userid": 0, "time": 1.0, "value": 2.1}])
+        );
+    }
 
-        let difference = union.difference(&end_with_3_view);
-        assert_eq!(difference.groups.len(), 0);
+    #[test]
+    fn float_precision() {
+        let attr = Attr::Float(0.23456);
+        assert_eq!(attr.to_string(), "0.23456");
+        assert_eq!(format!("{:.2}", attr), "0.23");
+        assert_eq!(attr.to_string_with_precision(Some(4)), "0.2346");
+        assert_eq!(Attr::Int(3).to_string_with_precision(Some(2)), "3");
+
+        let headers = ["userid", "time", "f", "s"];
+        let ctx = make_a_ctx();
+        let record =
+            Record::new(&ctx, zip(headers, ["0", "1", "0.23456", "x.yz"]).collect()).unwrap();
+        let collection = Collection::new(vec![&record]);
+        let mut output = Vec::new();
+        collection
+            .write_csv_with_precision(&mut output, &["f", "s"], Some(2))
+            .unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "f,s\n0.23,x.yz\n");
+        assert!(collection
+            .to_ascii_table_with_precision(&["f"], Some(4))
+            .contains("| 0.2346 |"));
+
+        let fold_result = collection.fold(FoldOperation::SUM("f".into())).unwrap();
+        let mut output = Vec::new();
+        fold_result
+            .write_csv_with_precision(&mut output, Some(2))
+            .unwrap();
+        assert!(String::from_utf8(output).unwrap().ends_with(",0.23\n"));
     }
 
     #[test]
-    fn fold_collections() {
-        let headers = vec!["userid", "time", "i"];
+    fn merge_collections_with() {
+        let headers = ["userid", "time", "i"];
         let ctx = make_a_ctx();
-        let records = vec![
+        let records: Vec<_> = [
             vec!["0", "1", "233"],
             vec!["0", "1", "23"],
-            vec!["0", "1", "2333"],
-            vec!["0", "1", "0"],
-            vec!["0", "1", "-28"],
-            vec!["0", "1", "233"],
+            vec!["1", "1", "-28"],
             vec!["0", "1", "366"],
-            vec!["0", "1", "243"],
-        ];
-        let records: Vec<_> = records
-            .into_iter()
-            .map(|raw_record| {
-                Record::new(
-                    &ctx,
-                    zip(headers.iter(), raw_record.into_iter())
-                        .map(|(x, y)| (x.to_owned(), y))
-                        .collect(),
+            vec!["2", "1", "243"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let ours = Collection::new(records[..3].iter().collect());
+        let theirs = Collection::new(records[3..].iter().collect());
+        let sizes = |collection: &Collection| -> Vec<(i32, usize)> {
+            let mut sizes: Vec<(i32, usize)> = collection
+                .groups
+                .values()
+                .map(
+                    |group| match group.key().iter().find(|(name, _)| name == "userid") {
+                        Some((_, Attr::Int(userid))) => (*userid, group.records.len()),
+                        _ => unreachable!(),
+                    },
                 )
-            })
-            .collect();
-        let view = records.iter().collect();
-        let collection = Collection::new(view);
+                .collect();
+            sizes.sort();
+            sizes
+        };
 
-        let count_result = collection.count();
-        assert_eq!(count_result.result.len(), 1);
-        assert_eq!(count_result.result.iter().next().unwrap().1, &Attr::Int(8));
+        let merged = ours.clone().merge_with(&theirs, |group, _| group.clone());
+        assert_eq!(sizes(&merged), [(0, 2), (1, 1), (2, 1)]);
+        let user_0 = merged
+            .groups
+            .values()
+            .find(|group| group.records.len() == 2);
+        assert!(user_0
+            .unwrap()
+            .records()
+            .all(|record| record != &records[3]));
 
-        let sum_result = collection.sum("i");
-        assert_eq!(
-            sum_result.result.iter().next().unwrap().1,
-            &Attr::Float(3403f32)
-        );
+        let union = ours.clone().merge_with(&theirs, Group::union);
+        assert_eq!(sizes(&union), [(0, 3), (1, 1), (2, 1)]);
+        assert_eq!(sizes(&ours.clone().union(&theirs)), sizes(&union));
 
-        let avg_result = collection.avg("i");
-        assert_eq!(
-            avg_result.result.iter().next().unwrap().1,
-            &Attr::Float(425.375)
-        );
+        let emptied = ours.merge_with(&theirs, |group, _| Group::new(group.id, Vec::new()));
+        assert_eq!(sizes(&emptied), [(1, 1), (2, 1)]);
+        emptied.validate().unwrap();
     }
 
     #[test]
-    #[should_panic(expected = "Error: header `x' is not found in context info")]
-    fn unexpected_header() {
+    #[should_panic(expected = "merged group should keep its id")]
+    fn merge_with_another_id() {
+        let headers = ["userid", "time", "i"];
         let ctx = make_a_ctx();
-        let headers = ["userid", "time", "x"];
-        let record = vec!["0", "0", "0"];
-        Record::new(&ctx, zip(headers.into_iter(), record.into_iter()).collect());
+        let records: Vec<_> = [
+            vec!["0", "1", "233"],
+            vec!["0", "1", "23"],
+            vec!["1", "1", "0"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let ours = Collection::new(records[..1].iter().collect());
+        let theirs = Collection::new(records[1..].iter().collect());
+        let stranger = theirs.groups[&records[2].group_id()].clone();
+        ours.merge_with(&theirs, |_, _| stranger.clone());
     }
 
     #[test]
-    #[should_panic(expected = "Error: expect int when parsing attribute `i', which value is `true'")]
-    fn invalid_attr_type() {
-        let ctx = make_a_ctx();
-        let headers = ["userid", "time", "i"];
-        let record = vec!["0", "0", "true"];
-        Record::new(&ctx, zip(headers.into_iter(), record.into_iter()).collect());
+    fn intern_categories() {
+        let mut ctx = Ctx::new();
+        ctx.add_attr_type("userid", Attr::Int(0), Some(ComponentRule::Unique));
+        ctx.add_attr_type("status", Attr::Category(0, "".into()), None);
+        ctx.add_attr_type("i", Attr::Int(0), None);
+        let headers = ["userid", "status", "i"];
+        let records: Vec<_> = [
+            vec!["0", "open", "1"],
+            vec!["0", "closed", "2"],
+            vec!["0", "open", "3"],
+            vec!["1", "closed", "4"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect();
+        let status: Vec<&Attr> = records
+            .iter()
+            .map(|record| &record.attrs["status"])
+            .collect();
+        assert_eq!(status[0], status[2]);
+        assert_eq!(status[1], status[3]);
+        assert_ne!(status[0], status[1]);
+        let Attr::Category(open, name) = status[0] else {
+            panic!("`status' should be a category")
+        };
+        assert_eq!(ctx.category_name(*open).as_deref(), Some("open"));
+        assert!(Arc::ptr_eq(name, &ctx.category_name(*open).unwrap()));
+        assert_eq!(ctx.category_name(2), None);
+        assert_eq!(status[1].to_string(), "closed");
+
+        // Filter values are looked up, not interned
+        assert_eq!(ctx.parse_attr("status", "open").unwrap(), *status[0]);
+        let pending = ctx.parse_attr("status", "pending").unwrap();
+        assert_eq!(pending.to_string(), "pending");
+        assert_ne!(pending, ctx.parse_attr("status", "stalled").unwrap());
+        assert!(status.iter().all(|&status| *status != pending));
+        assert_eq!(ctx.category_name(2), None);
+
+        let collection = Collection::new(records.iter().collect());
+        let result = collection
+            .fold(FoldOperation::MODE("status".into()))
+            .unwrap();
+        let user_0 = records[0].group_id();
+        assert_eq!(result.get(user_0), Some(status[0]));
+        assert!(result.to_markdown().contains("| open |"));
+        let mut output = Vec::new();
+        collection
+            .write_csv(&mut output, &["userid", "status"])
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let mut lines: Vec<&str> = output.lines().collect();
+        lines.sort();
+        assert_eq!(
+            lines,
+            ["0,closed", "0,open", "0,open", "1,closed", "userid,status"]
+        );
+        let result = collection
+            .fold(FoldOperation::COUNT_DISTINCT("status".into()))
+            .unwrap();
+        assert_eq!(result.get(user_0), Some(&Attr::Int(2)));
+        assert!(collection
+            .fold(FoldOperation::SUM("status".into()))
+            .is_err());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn category_to_json() {
+        use serde_json::json;
+
+        let mut ctx = Ctx::new();
+        ctx.add_attr_type("userid", Attr::Int(0), Some(ComponentRule::Unique));
+        ctx.add_attr_type("status", Attr::Category(0, "".into()), None);
+        let record = Record::new(&ctx, vec![("userid", "0"), ("status", "open")]).unwrap();
+        let collection = Collection::new(vec![&record]);
+        assert_eq!(
+            collection.to_json(),
+            json!([{"userid": 0, "status": "open"}])
+        );
+        let result = collection
+            .fold(FoldOperation::MODE("status".into()))
+            .unwrap();
+        assert_eq!(result.to_json(), json!([{"userid": 0, "value": "open"}]));
     }
 }
@@ -0,0 +1,57 @@
+use crate::data_represent::{FoldError, GroupCollision, ParseError};
+use thiserror::Error;
+
+/// Any failure of the crate, so it can be embedded without panicking
+#[derive(Error, Debug)]
+pub enum Error {
+    /// An attribute which has no declared type in `Ctx`
+    #[error("Error: header `{0}' is not found in context info")]
+    MissingHeader(String),
+    /// A group-by attribute which is absent from the record
+    #[error("Error: key attribute is not found")]
+    MissingGroupKey(String),
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+    /// Parse error of a record read from a table, the row is the line the record starts at,
+    /// header row included
+    #[error(
+        "row {row}, column `{}': expected {}, got `{}'",
+        .error.header,
+        .error.expected,
+        .error.raw_attr
+    )]
+    ParseAt { row: u64, error: ParseError },
+    #[error(transparent)]
+    Fold(#[from] FoldError),
+    #[error(transparent)]
+    GroupCollision(#[from] GroupCollision),
+    /// A pivot whose fold operation folds another attribute than its value, see
+    /// `Collection::pivot`
+    #[error("Error: pivot of `{value}' can't fold `{attr_name}'")]
+    PivotValue { value: String, attr_name: String },
+    /// A collection whose groups break an invariant, see `Collection::validate`
+    #[error("Error: invalid collection, {0}")]
+    InvalidCollection(String),
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    /// Malformed schema file, see `Ctx::from_schema_file`
+    #[cfg(feature = "schema")]
+    #[error("Error: invalid schema, {0}")]
+    Schema(String),
+    /// Malformed ndjson line, lines are numbered from 1
+    #[cfg(feature = "ndjson")]
+    #[error("line {0}: {1}")]
+    Ndjson(usize, serde_json::Error),
+}
+
+impl Error {
+    /// Attach the row of the record to a parse error, other errors are kept as they are
+    pub fn at_row(self, row: u64) -> Self {
+        match self {
+            Error::Parse(error) => Error::ParseAt { row, error },
+            _ => self,
+        }
+    }
+}
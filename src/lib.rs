@@ -0,0 +1,6 @@
+pub mod data_represent;
+pub mod error;
+pub mod pipeline;
+pub mod reader;
+#[cfg(feature = "schema")]
+mod schema;
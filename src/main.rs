@@ -1,13 +1,345 @@
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use generic_table_processor::data_represent::{
+    CmpOp, Collection, CollectionBuilder, ComponentRule, Ctx, Estimator, FoldOperation,
+    JoinedRecord, Predicate, Record,
+};
+use generic_table_processor::reader::TableReader;
 use std::error::Error;
+use std::fs::File;
 use std::io;
+use std::process;
 
-mod data_represent;
+/// Filter, group and fold a table, records are read from `PATH`, or stdin when it's absent or
+/// `-`. Without a command, records are printed as a table
+#[derive(Parser)]
+#[command(name = "generic-table-processor")]
+struct Cli {
+    #[command(flatten)]
+    table: TableArgs,
+    /// Decimals floats are rounded to in the output, they're written in full without it
+    #[arg(short, long, global = true)]
+    precision: Option<usize>,
+    #[command(subcommand)]
+    command: Option<Command>,
+    path: Option<String>,
+}
+
+/// How tables are read, shared by every command
+#[derive(Args)]
+struct TableArgs {
+    /// Field delimiter, a single byte like `;` or `\t`
+    #[arg(short, long, default_value = ",", value_parser = parse_delimiter, global = true)]
+    delimiter: u8,
+    /// Column names of a headerless table, like `userid,time,i`
+    #[arg(short = 'H', long, value_delimiter = ',', global = true)]
+    headers: Option<Vec<String>>,
+    /// Columns grouped by, each of their distinct values is a group
+    #[arg(short, long, value_delimiter = ',', global = true)]
+    group_by: Vec<String>,
+    /// Schema file declaring attribute types and group-by rules, they are inferred without it
+    #[cfg(feature = "schema")]
+    #[arg(short, long, global = true)]
+    schema: Option<String>,
+    /// Decompress gzip input, files ending in `.gz` are decompressed without it
+    #[cfg(feature = "gzip")]
+    #[arg(short = 'z', long, global = true)]
+    gzip: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print records as a table, grouped
+    Show { path: Option<String> },
+    /// Write the records matching a condition as csv
+    Filter {
+        #[command(flatten)]
+        cond: FilterArgs,
+        path: Option<String>,
+    },
+    /// Write the record count of each group as csv
+    Group { path: Option<String> },
+    /// Write the fold result of each group as csv
+    Fold {
+        #[arg(long)]
+        op: Op,
+        /// Folded column, every operation but `count` needs it
+        #[arg(long)]
+        column: Option<String>,
+        path: Option<String>,
+    },
+    /// Write the inner join of two tables on a column as csv
+    Join {
+        #[arg(long)]
+        on: String,
+        left: String,
+        right: String,
+    },
+}
+
+#[derive(Args)]
+struct FilterArgs {
+    #[arg(long)]
+    column: String,
+    #[command(flatten)]
+    cmp: Cmp,
+    /// Match string patterns case-insensitively
+    #[arg(short, long)]
+    ignore_case: bool,
+}
+
+/// Exactly one condition on the column, values are parsed as its type
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+struct Cmp {
+    #[arg(long)]
+    eq: Option<String>,
+    #[arg(long)]
+    ne: Option<String>,
+    #[arg(long)]
+    lt: Option<String>,
+    #[arg(long)]
+    le: Option<String>,
+    #[arg(long)]
+    gt: Option<String>,
+    #[arg(long)]
+    ge: Option<String>,
+    #[arg(long)]
+    contains: Option<String>,
+    #[arg(long)]
+    starts_with: Option<String>,
+    #[arg(long)]
+    ends_with: Option<String>,
+}
+
+/// Fold operations of the command line, spread folds use the sample estimator
+#[derive(Clone, Copy, ValueEnum)]
+enum Op {
+    Sum,
+    Avg,
+    Count,
+    CountDistinct,
+    Mode,
+    Variance,
+    Stddev,
+    Rms,
+    Median,
+}
+
+impl Op {
+    fn operation(self, column: Option<String>) -> Result<FoldOperation, String> {
+        if let Op::Count = self {
+            return Ok(FoldOperation::COUNT);
+        }
+        let name = self.to_possible_value().unwrap().get_name().to_owned();
+        let column = column.ok_or_else(|| format!("Error: `--op {}' needs `--column'", name))?;
+        Ok(match self {
+            Op::Sum => FoldOperation::SUM(column),
+            Op::Avg => FoldOperation::AVG(column),
+            Op::Count => unreachable!(),
+            Op::CountDistinct => FoldOperation::COUNT_DISTINCT(column),
+            Op::Mode => FoldOperation::MODE(column),
+            Op::Variance => FoldOperation::VARIANCE(column, Estimator::Sample),
+            Op::Stddev => FoldOperation::STDDEV(column, Estimator::Sample),
+            Op::Rms => FoldOperation::RMS(column),
+            Op::Median => FoldOperation::PERCENTILE {
+                attr_name: column,
+                p: 0.5,
+            },
+        })
+    }
+}
+
+/// A delimiter is a single byte, `\t` is accepted for tab
+fn parse_delimiter(raw: &str) -> Result<u8, String> {
+    match raw.as_bytes() {
+        b"\\t" => Ok(b'\t'),
+        [delimiter] => Ok(*delimiter),
+        _ => Err(format!("delimiter should be a single byte, got `{}'", raw)),
+    }
+}
+
+/// Records of a table with its headers, and the context they're parsed by
+struct Table {
+    headers: Vec<String>,
+    records: Vec<Record>,
+    ctx: Ctx,
+}
+
+impl Table {
+    fn read(path: Option<&str>, args: &TableArgs) -> Result<Self, Box<dyn Error>> {
+        let input: Box<dyn io::Read> = match path {
+            // `-` stands for stdin
+            None | Some("-") => Box::new(io::stdin()),
+            Some(path) => Box::new(
+                File::open(path).map_err(|e| format!("Error: can't open `{}': {}", path, e))?,
+            ),
+        };
+        #[cfg(feature = "gzip")]
+        let input: Box<dyn io::Read> = {
+            use generic_table_processor::reader::{gunzip, is_gzip};
+            if args.gzip || path.is_some_and(is_gzip) {
+                Box::new(gunzip(input))
+            } else {
+                input
+            }
+        };
+        let mut reader = TableReader::new();
+        reader.set_delimiter(args.delimiter);
+        if let Some(headers) = &args.headers {
+            reader.set_headers(&headers.iter().map(|x| x.as_str()).collect::<Vec<_>>());
+        }
+        // The table is read twice, once to infer its types and once to parse its records
+        let data = io::read_to_string(input)?;
+        let mut rdr = reader.csv_reader(data.as_bytes());
+        let headers: Vec<String> = match &args.headers {
+            Some(headers) => headers.clone(),
+            None => rdr.headers()?.iter().map(|x| x.into()).collect(),
+        };
+        let names: Vec<&str> = headers.iter().map(|x| x.as_str()).collect();
+        let rows = rdr.records().collect::<Result<Vec<_>, _>>()?;
+        let raw_rows: Vec<Vec<&str>> = rows.iter().map(|row| row.iter().collect()).collect();
+
+        // Types are inferred from the table itself, unless a schema declares them
+        #[cfg(feature = "schema")]
+        let mut ctx = match &args.schema {
+            Some(schema) => Ctx::from_schema_file(schema)?,
+            None => Ctx::infer(&names, &raw_rows),
+        };
+        #[cfg(not(feature = "schema"))]
+        let mut ctx = Ctx::infer(&names, &raw_rows);
+        for name in &args.group_by {
+            ctx.set_group_by(name, ComponentRule::Unique);
+        }
+        let records = reader.read_records(&ctx, data.as_bytes())?;
+        Ok(Table {
+            headers,
+            records,
+            ctx,
+        })
+    }
+
+    fn headers(&self) -> Vec<&str> {
+        self.headers.iter().map(|x| x.as_str()).collect()
+    }
+
+    fn collection(&self) -> Result<Collection<'_>, Box<dyn Error>> {
+        let mut builder = CollectionBuilder::new();
+        for record in self.records.iter() {
+            builder.push(record)?;
+        }
+        Ok(builder.finish())
+    }
+}
+
+impl FilterArgs {
+    fn predicate(self, ctx: &Ctx) -> Result<Predicate, Box<dyn Error>> {
+        let FilterArgs {
+            column,
+            cmp,
+            ignore_case: case_insensitive,
+        } = self;
+        let compare = |op: CmpOp, raw: String| -> Result<Predicate, Box<dyn Error>> {
+            Ok(Predicate::Cmp {
+                val: ctx.parse_attr(&column, &raw)?,
+                attr_name: column.clone(),
+                op,
+            })
+        };
+        Ok(match cmp {
+            Cmp { eq: Some(raw), .. } => compare(CmpOp::Eq, raw)?,
+            Cmp { ne: Some(raw), .. } => compare(CmpOp::Ne, raw)?,
+            Cmp { lt: Some(raw), .. } => compare(CmpOp::Lt, raw)?,
+            Cmp { le: Some(raw), .. } => compare(CmpOp::Le, raw)?,
+            Cmp { gt: Some(raw), .. } => compare(CmpOp::Gt, raw)?,
+            Cmp { ge: Some(raw), .. } => compare(CmpOp::Ge, raw)?,
+            Cmp {
+                contains: Some(pattern),
+                ..
+            } => Predicate::Contains {
+                attr_name: column,
+                pattern,
+                case_insensitive,
+            },
+            Cmp {
+                starts_with: Some(pattern),
+                ..
+            } => Predicate::StartsWith {
+                attr_name: column,
+                pattern,
+                case_insensitive,
+            },
+            Cmp {
+                ends_with: Some(pattern),
+                ..
+            } => Predicate::EndsWith {
+                attr_name: column,
+                pattern,
+                case_insensitive,
+            },
+            // clap requires exactly one condition
+            _ => unreachable!(),
+        })
+    }
+}
+
+fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+    let args = &cli.table;
+    let precision = cli.precision;
+    match cli.command {
+        None => show(cli.path.as_deref(), args, precision),
+        Some(Command::Show { path }) => show(path.as_deref(), args, precision),
+        Some(Command::Filter { cond, path }) => {
+            let table = Table::read(path.as_deref(), args)?;
+            let predicate = cond.predicate(&table.ctx)?;
+            let collection = table.collection()?.filter_records(predicate);
+            collection.write_csv_with_precision(io::stdout(), &table.headers(), precision)?;
+            Ok(())
+        }
+        Some(Command::Group { path }) => {
+            let table = Table::read(path.as_deref(), args)?;
+            table
+                .collection()?
+                .fold(FoldOperation::COUNT)?
+                .write_csv_sorted_by_key(io::stdout(), precision)?;
+            Ok(())
+        }
+        Some(Command::Fold { op, column, path }) => {
+            let op = op.operation(column)?;
+            let table = Table::read(path.as_deref(), args)?;
+            table
+                .collection()?
+                .fold(op)?
+                .write_csv_sorted_by_key(io::stdout(), precision)?;
+            Ok(())
+        }
+        Some(Command::Join { on, left, right }) => {
+            let left = Table::read(Some(&left), args)?;
+            let right = Table::read(Some(&right), args)?;
+            let joined = left.collection()?.inner_join(&right.collection()?, &on);
+            JoinedRecord::write_csv_with_precision(&joined, io::stdout(), precision)?;
+            Ok(())
+        }
+    }
+}
+
+fn show(
+    path: Option<&str>,
+    args: &TableArgs,
+    precision: Option<usize>,
+) -> Result<(), Box<dyn Error>> {
+    let table = Table::read(path, args)?;
+    print!(
+        "{}",
+        table
+            .collection()?
+            .to_ascii_table_with_precision(&table.headers(), precision)
+    );
+    Ok(())
+}
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let mut rdr = csv::Reader::from_reader(io::stdin());
-    for e in rdr.records() {
-        let record = e?;
-        println!("{:?}", record);
+fn main() {
+    if let Err(e) = run(Cli::parse()) {
+        eprintln!("{}", e);
+        process::exit(1);
     }
-    return Ok(());
 }
@@ -0,0 +1,135 @@
+use crate::data_represent::{
+    Attr, CmpOp, Collection, Ctx, FoldOperation, FoldResult, Predicate, Record,
+};
+use crate::error::Error;
+
+/// Chains the operations of a `Collection`, every stage returns a `Result`, so a pipeline can be
+/// written as `pipeline.filter(...)?.intersection(...)?.fold(...)?` without panicking
+pub struct Pipeline<'a> {
+    collection: Collection<'a>,
+}
+
+impl<'a> From<Collection<'a>> for Pipeline<'a> {
+    fn from(collection: Collection<'a>) -> Self {
+        Pipeline { collection }
+    }
+}
+
+impl<'a> Pipeline<'a> {
+    /// Group the records, fails if two different group keys collide into the same group id
+    pub fn new(records: Vec<&'a Record>) -> Result<Self, Error> {
+        Ok(Collection::try_new(records)?.into())
+    }
+
+    pub fn collection(&self) -> &Collection<'a> {
+        &self.collection
+    }
+
+    pub fn into_collection(self) -> Collection<'a> {
+        self.collection
+    }
+
+    pub fn filter(self, predicate: impl Into<Predicate>) -> Result<Self, Error> {
+        Ok(self.collection.filter_records(predicate).into())
+    }
+
+    pub fn filter_with<F: Fn(&Record) -> bool>(self, pred: F) -> Result<Self, Error> {
+        Ok(self.collection.filter_with(pred).into())
+    }
+
+    pub fn top_n(self, attr_name: &str, n: usize, ascending: bool) -> Result<Self, Error> {
+        Ok(self.collection.top_n(attr_name, n, ascending).into())
+    }
+
+    pub fn intersection(self, other: &Collection<'a>) -> Result<Self, Error> {
+        Ok(self.collection.intersection(other).into())
+    }
+
+    pub fn union(self, other: &Collection<'a>) -> Result<Self, Error> {
+        Ok(self.collection.union(other).into())
+    }
+
+    pub fn difference(self, other: &Collection<'a>) -> Result<Self, Error> {
+        Ok(self.collection.difference(other).into())
+    }
+
+    pub fn dedup_by_value(self) -> Result<Self, Error> {
+        Ok(self.collection.dedup_by_value().into())
+    }
+
+    /// Keep the groups whose fold result satisfies `cmp` against `threshold`, see
+    /// `Collection::filter_groups_by_fold`
+    pub fn having(self, op: FoldOperation, cmp: CmpOp, threshold: Attr) -> Result<Self, Error> {
+        Ok(self
+            .collection
+            .filter_groups_by_fold(op, cmp, threshold)?
+            .into())
+    }
+
+    /// Group the records again by the rules of `ctx`, see `Collection::regroup`
+    pub fn regroup(self, ctx: &Ctx) -> Result<Self, Error> {
+        Ok(self.collection.regroup(ctx)?.into())
+    }
+
+    pub fn fold(&self, op: FoldOperation) -> Result<FoldResult<'_>, Error> {
+        Ok(self.collection.fold(op)?)
+    }
+
+    pub fn fold_many(&self, ops: Vec<FoldOperation>) -> Result<Vec<FoldResult<'_>>, Error> {
+        Ok(self.collection.fold_many(ops)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_represent::ComponentRule;
+    use std::iter::zip;
+
+    fn make_records() -> Vec<Record> {
+        let mut ctx = Ctx::new();
+        ctx.add_attr_type("userid", Attr::Int(0), Some(ComponentRule::Unique));
+        ctx.add_attr_type("i", Attr::Int(0), None);
+        ctx.add_attr_type("s", Attr::Str(String::new()), None);
+        let headers = ["userid", "i", "s"];
+        [
+            vec!["0", "1", "a"],
+            vec!["0", "5", "b"],
+            vec!["1", "7", "c"],
+            vec!["1", "2", "d"],
+            vec!["2", "9", "e"],
+        ]
+        .into_iter()
+        .map(|raw_record| Record::new(&ctx, zip(headers, raw_record).collect()).unwrap())
+        .collect()
+    }
+
+    #[test]
+    fn multi_stage_pipeline() -> Result<(), Error> {
+        let records = make_records();
+        let everyone = Collection::new(records.iter().collect());
+        let pipeline = Pipeline::new(records.iter().collect())?
+            .filter(Predicate::Cmp {
+                attr_name: "i".into(),
+                val: Attr::Int(1),
+                op: CmpOp::Gt,
+            })?
+            .intersection(&everyone)?
+            .having(FoldOperation::COUNT, CmpOp::Lt, Attr::Int(2))?;
+        let mut sums: Vec<Attr> = pipeline
+            .fold(FoldOperation::SUM("i".into()))?
+            .iter()
+            .map(|(_, attr)| attr.clone())
+            .collect();
+        sums.sort();
+        assert_eq!(sums, [Attr::Float(5.0), Attr::Float(9.0)]);
+
+        let err = pipeline.fold(FoldOperation::AVG("s".into())).err().unwrap();
+        assert!(matches!(err, Error::Fold(ref e) if e.attr_name == "s"));
+        assert!(matches!(
+            pipeline.having(FoldOperation::SUM("s".into()), CmpOp::Lt, Attr::Int(1)),
+            Err(Error::Fold(_))
+        ));
+        Ok(())
+    }
+}
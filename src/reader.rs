@@ -0,0 +1,292 @@
+use crate::data_represent::{Ctx, Record};
+use crate::error::Error;
+use std::io;
+use std::iter::zip;
+#[cfg(feature = "gzip")]
+use std::path::Path;
+
+/// Options of reading a table, comma separated with a header row by default
+pub struct TableReader {
+    delimiter: u8,
+    quote: u8,
+    double_quote: bool,
+    /// Rows may have fewer or more fields than the header row
+    flexible: bool,
+    /// Column names of a headerless table
+    headers: Option<Vec<String>>,
+}
+
+impl Default for TableReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TableReader {
+    pub fn new() -> Self {
+        TableReader {
+            delimiter: b',',
+            quote: b'"',
+            double_quote: true,
+            flexible: false,
+            headers: None,
+        }
+    }
+
+    /// Field delimiter, like `b'\t'` for tsv or `b';'`
+    pub fn set_delimiter(&mut self, delimiter: u8) {
+        self.delimiter = delimiter;
+    }
+
+    /// Quote character of fields, `"` by default. Quoted fields may contain delimiters and
+    /// newlines
+    pub fn set_quote(&mut self, quote: u8) {
+        self.quote = quote;
+    }
+
+    /// Whether two quotes in a quoted field are an escaped quote, like `""`, enabled by default
+    pub fn set_double_quote(&mut self, double_quote: bool) {
+        self.double_quote = double_quote;
+    }
+
+    /// Accept rows whose field counts differ from the header row, missing attributes are absent
+    /// from their records and extra fields are dropped
+    pub fn set_flexible(&mut self, flexible: bool) {
+        self.flexible = flexible;
+    }
+
+    /// Read a table without a header row, its columns are named `headers` by position
+    pub fn set_headers(&mut self, headers: &[&str]) {
+        self.headers = Some(headers.iter().map(|&x| x.into()).collect());
+    }
+
+    /// A csv reader configured with the options
+    pub fn csv_reader<R: io::Read>(&self, rdr: R) -> csv::Reader<R> {
+        csv::ReaderBuilder::new()
+            .delimiter(self.delimiter)
+            .quote(self.quote)
+            .double_quote(self.double_quote)
+            .flexible(self.flexible)
+            .has_headers(self.headers.is_none())
+            .from_reader(rdr)
+    }
+
+    /// Read all records, attributes are named after the header row, or the names set by
+    /// `set_headers`. Parse errors tell the row of the record, see `Error::ParseAt`
+    pub fn read_records<R: io::Read>(&self, ctx: &Ctx, rdr: R) -> Result<Vec<Record>, Error> {
+        let mut rdr = self.csv_reader(rdr);
+        let headers = match &self.headers {
+            Some(headers) => csv::StringRecord::from(headers.clone()),
+            None => rdr.headers()?.clone(),
+        };
+        rdr.records()
+            .map(|row| {
+                let row = row?;
+                Record::new(ctx, zip(&headers, &row).collect()).map_err(|e| match row.position() {
+                    Some(position) => e.at_row(position.line()),
+                    None => e,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Whether the path is of a gzip file, like `data.csv.gz`
+#[cfg(feature = "gzip")]
+pub fn is_gzip(path: impl AsRef<Path>) -> bool {
+    path.as_ref()
+        .extension()
+        .is_some_and(|extension| extension == "gz")
+}
+
+/// Decompress gzip input for `TableReader`, concatenated gzip members are read as one stream
+#[cfg(feature = "gzip")]
+pub fn gunzip<R: io::Read>(rdr: R) -> flate2::read::MultiGzDecoder<R> {
+    flate2::read::MultiGzDecoder::new(rdr)
+}
+
+/// Read ndjson records, each line is a json object which maps headers to values. Values are
+/// parsed by `Ctx` as if they were csv cells: strings are taken as is, `null` is an empty cell,
+/// and other values are json text, like `3` or `true`. Blank lines are skipped.
+///
+/// Every line gets its own result, so malformed lines can be reported without aborting
+#[cfg(feature = "ndjson")]
+pub fn read_ndjson<'a, R: io::BufRead + 'a>(
+    ctx: &'a Ctx,
+    rdr: R,
+) -> impl Iterator<Item = Result<Record, Error>> + 'a {
+    rdr.lines()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(move |(i, line)| {
+            let line = line.map_err(|e| Error::Ndjson(i + 1, serde_json::Error::io(e)))?;
+            let object: serde_json::Map<String, serde_json::Value> =
+                serde_json::from_str(&line).map_err(|e| Error::Ndjson(i + 1, e))?;
+            let raw_attrs: Vec<(&str, String)> = object
+                .iter()
+                .map(|(header, value)| {
+                    let raw_attr = match value {
+                        serde_json::Value::String(v) => v.to_owned(),
+                        serde_json::Value::Null => String::new(),
+                        _ => value.to_string(),
+                    };
+                    (header.as_str(), raw_attr)
+                })
+                .collect();
+            let raw_record = raw_attrs
+                .iter()
+                .map(|(header, raw_attr)| (*header, raw_attr.as_str()))
+                .collect();
+            Record::new(ctx, raw_record).map_err(|e| e.at_row(i as u64 + 1))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_represent::{Attr, Collection, ComponentRule};
+    use std::fs::File;
+
+    fn make_a_ctx() -> Ctx {
+        let mut ctx = Ctx::new();
+        ctx.add_attr_type("userid", Attr::Int(0), Some(ComponentRule::Unique));
+        ["time", "a", "b", "c"]
+            .iter()
+            .for_each(|attr_name| ctx.add_attr_type(attr_name, Attr::Int(0), None));
+        ctx
+    }
+
+    fn write_csv(records: &[Record]) -> String {
+        let mut wtr = Vec::new();
+        Collection::new(records.iter().collect())
+            .write_csv(&mut wtr, &["userid", "time", "a", "b", "c"])
+            .unwrap();
+        String::from_utf8(wtr).unwrap()
+    }
+
+    #[test]
+    fn read_tsv() {
+        let ctx = make_a_ctx();
+        let csv = TableReader::new()
+            .read_records(&ctx, File::open("test-cases/base.csv").unwrap())
+            .unwrap();
+        let mut reader = TableReader::new();
+        reader.set_delimiter(b'\t');
+        let tsv = reader
+            .read_records(&ctx, File::open("test-cases/base.tsv").unwrap())
+            .unwrap();
+        assert_eq!(tsv.len(), 4);
+        assert_eq!(write_csv(&tsv), write_csv(&csv));
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn read_gzip() {
+        let ctx = make_a_ctx();
+        let csv = TableReader::new()
+            .read_records(&ctx, File::open("test-cases/base.csv").unwrap())
+            .unwrap();
+        assert!(is_gzip("test-cases/base.csv.gz"));
+        assert!(!is_gzip("test-cases/base.csv"));
+        let gz = TableReader::new()
+            .read_records(&ctx, gunzip(File::open("test-cases/base.csv.gz").unwrap()))
+            .unwrap();
+        assert_eq!(gz.len(), 4);
+        assert_eq!(write_csv(&gz), write_csv(&csv));
+    }
+
+    #[test]
+    fn read_headerless() {
+        let ctx = make_a_ctx();
+        let csv = TableReader::new()
+            .read_records(&ctx, File::open("test-cases/base.csv").unwrap())
+            .unwrap();
+        let mut reader = TableReader::new();
+        reader.set_headers(&["userid", "time", "a", "b", "c"]);
+        let headerless = reader
+            .read_records(&ctx, File::open("test-cases/headerless.csv").unwrap())
+            .unwrap();
+        assert_eq!(headerless.len(), 4);
+        assert_eq!(write_csv(&headerless), write_csv(&csv));
+    }
+
+    #[test]
+    fn parse_error_row() {
+        let ctx = make_a_ctx();
+        let table = "userid,time,a,b,c\n0,0,1,2,3\n0,1,true,5,6\n";
+        let err = TableReader::new()
+            .read_records(&ctx, table.as_bytes())
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::ParseAt { row: 3, .. }));
+        assert_eq!(
+            err.to_string(),
+            "row 3, column `a': expected int, got `true'"
+        );
+    }
+
+    #[test]
+    fn read_quoted() {
+        let mut ctx = Ctx::new();
+        ctx.add_attr_type("userid", Attr::Int(0), Some(ComponentRule::Unique));
+        ctx.add_attr_type("description", Attr::Str(String::new()), None);
+        let records = TableReader::new()
+            .read_records(&ctx, File::open("test-cases/quoted.csv").unwrap())
+            .unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(
+            records[0].get("description"),
+            Some(&Attr::Str("red, green\nand blue".into()))
+        );
+        assert_eq!(
+            records[1].get("description"),
+            Some(&Attr::Str("say \"hi\"".into()))
+        );
+        assert_eq!(
+            records[2].get("description"),
+            Some(&Attr::Str("plain".into()))
+        );
+
+        let mut reader = TableReader::new();
+        reader.set_quote(b'\'');
+        reader.set_flexible(true);
+        let records = reader
+            .read_records(&ctx, "userid,description\n0,'a, b'\n1\n".as_bytes())
+            .unwrap();
+        assert_eq!(
+            records[0].get("description"),
+            Some(&Attr::Str("a, b".into()))
+        );
+        assert_eq!(records[1].get("description"), None);
+        assert!(TableReader::new()
+            .read_records(&ctx, "userid,description\n1\n".as_bytes())
+            .is_err());
+    }
+
+    #[cfg(feature = "ndjson")]
+    #[test]
+    fn read_ndjson_lines() {
+        let ctx = make_a_ctx();
+        let lines = concat!(
+            r#"{"userid": 0, "time": 0, "a": 1, "b": 2, "c": 3}"#,
+            "\n",
+            r#"{"userid": "0", "time": 1, "a": 4, "b": 5, "c": 6}"#,
+            "\n\n",
+            r#"{"userid": 2, "time": "#,
+            "\n",
+            r#"{"userid": 2, "time": 0, "a": true}"#,
+            "\n",
+        );
+        let results: Vec<_> = read_ndjson(&ctx, lines.as_bytes()).collect();
+        assert_eq!(results.len(), 4);
+        assert!(matches!(results[2], Err(Error::Ndjson(4, _))));
+        assert!(matches!(results[3], Err(Error::ParseAt { row: 5, .. })));
+
+        let records: Vec<Record> = results.into_iter().filter_map(Result::ok).collect();
+        assert_eq!(records.len(), 2);
+        let csv = TableReader::new()
+            .read_records(&ctx, File::open("test-cases/base.csv").unwrap())
+            .unwrap();
+        assert_eq!(write_csv(&records), write_csv(&csv[..2]));
+    }
+}
@@ -0,0 +1,225 @@
+use crate::data_represent::{Attr, ComponentRule, Ctx, Interval};
+use crate::error::Error;
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::Path;
+
+impl Ctx {
+    /// Read a `Ctx` from a json schema file, which is an object with a `columns` array. Each
+    /// column is an object of
+    ///
+    /// - `name`: the attribute name, required
+    /// - `type`: one of `int`, `int64`, `float`, `decimal` (with the `decimal` feature), `bool`,
+    ///   `str`, `category` and `datetime`, required
+    /// - `group_by`: `"unique"`, `{"interval": {"start": 1, "step": 3}}`,
+    ///   `{"str_normalized": {"lowercase": true, "trim": true}}` or `{"bins": [0, 18, 65]}`,
+    ///   optional. Steps should be positive and bins strictly ascending
+    /// - `nullable`: whether empty cells are `Null`, `false` by default
+    /// - `format`: parse format of a `datetime` column, like `"%Y-%m-%d %H:%M"`, optional
+    ///
+    /// ```json
+    /// {
+    ///     "columns": [
+    ///         {"name": "userid", "type": "int", "group_by": "unique"},
+    ///         {"name": "time", "type": "float", "group_by": {"interval": {"start": 1, "step": 3}}},
+    ///         {"name": "note", "type": "str", "nullable": true}
+    ///     ]
+    /// }
+    /// ```
+    ///
+    /// Columns are declared in order, unknown keys are rejected
+    pub fn from_schema_file(path: impl AsRef<Path>) -> Result<Ctx, Error> {
+        let schema: Value = serde_json::from_str(&fs::read_to_string(path)?)
+            .map_err(|e| Error::Schema(e.to_string()))?;
+        let columns = match schema.get("columns") {
+            Some(Value::Array(columns)) => columns,
+            _ => return Err(Error::Schema("`columns' should be an array".into())),
+        };
+        let mut ctx = Ctx::new();
+        for column in columns {
+            let column = column
+                .as_object()
+                .ok_or_else(|| Error::Schema("column should be an object".into()))?;
+            add_column(&mut ctx, column)?;
+        }
+        Ok(ctx)
+    }
+}
+
+fn add_column(ctx: &mut Ctx, column: &Map<String, Value>) -> Result<(), Error> {
+    let field = |key: &str| -> Result<&str, Error> {
+        column
+            .get(key)
+            .and_then(Value::as_str)
+            .ok_or_else(|| Error::Schema(format!("`{}' of column should be a string", key)))
+    };
+    let name = field("name")?;
+    if let Some(key) = column
+        .keys()
+        .find(|&key| !["name", "type", "group_by", "nullable", "format"].contains(&key.as_str()))
+    {
+        return Err(Error::Schema(format!(
+            "unknown key `{}' of column `{}'",
+            key, name
+        )));
+    }
+    let attr_type = match field("type")? {
+        "int" => Attr::Int(0),
+        "int64" => Attr::Int64(0),
+        "float" => Attr::Float(0.0),
+        #[cfg(feature = "decimal")]
+        "decimal" => Attr::Decimal(Default::default()),
+        "bool" => Attr::Bool(false),
+        "str" => Attr::Str(String::new()),
+        "category" => Attr::Category(0, "".into()),
+        "datetime" => Attr::DateTime(Default::default()),
+        other => {
+            return Err(Error::Schema(format!(
+                "unknown type `{}' of column `{}'",
+                other, name
+            )))
+        }
+    };
+    let group_by = column
+        .get("group_by")
+        .map(|rule| {
+            parse_rule(rule)
+                .ok_or_else(|| Error::Schema(format!("invalid `group_by' of column `{}'", name)))
+        })
+        .transpose()?;
+    ctx.add_attr_type(name, attr_type, group_by);
+    match column.get("nullable") {
+        None | Some(Value::Bool(false)) => (),
+        Some(Value::Bool(true)) => ctx.set_nullable(name),
+        Some(_) => {
+            return Err(Error::Schema(format!(
+                "`nullable' of column `{}' should be a bool",
+                name
+            )))
+        }
+    }
+    if column.contains_key("format") {
+        ctx.set_datetime_format(name, field("format")?);
+    }
+    Ok(())
+}
+
+fn parse_rule(rule: &Value) -> Option<ComponentRule> {
+    let int =
+        |object: &Value, key: &str| -> Option<i32> { object.get(key)?.as_i64()?.try_into().ok() };
+    let flag = |object: &Value, key: &str| -> Option<bool> {
+        object.get(key).map_or(Some(false), Value::as_bool)
+    };
+    match rule {
+        Value::String(rule) if rule == "unique" => Some(ComponentRule::Unique),
+        Value::Object(rule) if rule.len() == 1 => {
+            let (kind, args) = rule.iter().next()?;
+            match kind.as_str() {
+                "interval" => Some(ComponentRule::Interval(Interval::try_new(
+                    int(args, "start")?,
+                    int(args, "step")?,
+                )?)),
+                "str_normalized" => Some(ComponentRule::StrNormalized {
+                    lowercase: flag(args, "lowercase")?,
+                    trim: flag(args, "trim")?,
+                }),
+                "bins" => {
+                    let boundaries: Vec<i32> = args
+                        .as_array()?
+                        .iter()
+                        .map(|boundary| boundary.as_i64()?.try_into().ok())
+                        .collect::<Option<_>>()?;
+                    let ascending = boundaries.windows(2).all(|pair| pair[0] < pair[1]);
+                    ascending.then_some(ComponentRule::Bins(boundaries))
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data_represent::{Attr, ComponentRule, Ctx, Interval, Record};
+    use crate::error::Error;
+
+    #[test]
+    fn load_schema_file() {
+        let loaded = Ctx::from_schema_file("test-cases/schema.json").unwrap();
+        let mut ctx = Ctx::new();
+        ctx.add_attr_type("userid", Attr::Int(0), Some(ComponentRule::Unique));
+        ctx.add_attr_type(
+            "time",
+            Attr::Float(0.0),
+            Some(ComponentRule::Interval(Interval::new(1, 3))),
+        );
+        ctx.add_attr_type("at", Attr::DateTime(Default::default()), None);
+        ctx.set_datetime_format("at", "%Y-%m-%d %H:%M");
+        ctx.add_attr_type("note", Attr::Str(String::new()), None);
+        ctx.set_nullable("note");
+
+        let raw_records = [
+            vec![
+                ("userid", "0"),
+                ("time", "4.5"),
+                ("at", "2024-01-15 08:30"),
+                ("note", "hi"),
+            ],
+            vec![
+                ("userid", "0"),
+                ("time", "5"),
+                ("at", "2024-01-16 09:00"),
+                ("note", ""),
+            ],
+            vec![
+                ("userid", "1"),
+                ("time", "1"),
+                ("at", "2024-01-17 10:00"),
+                ("note", "x"),
+            ],
+        ];
+        for raw_record in raw_records {
+            let expected = Record::new(&ctx, raw_record.clone()).unwrap();
+            let record = Record::new(&loaded, raw_record).unwrap();
+            assert!(record == expected);
+            assert_eq!(record.group_id(), expected.group_id());
+        }
+        assert_eq!(
+            Record::new(&loaded, vec![("userid", "0"), ("time", "1"), ("note", "")])
+                .unwrap()
+                .get("note"),
+            Some(&Attr::Null)
+        );
+    }
+
+    #[test]
+    fn invalid_schema() {
+        let err = Ctx::from_schema_file("test-cases/missing.json")
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::Io(_)));
+        let err = Ctx::from_schema_file("test-cases/base.csv").err().unwrap();
+        assert!(matches!(err, Error::Schema(_)));
+    }
+
+    #[test]
+    fn invalid_group_by_rule() {
+        let path = std::env::temp_dir().join("generic-table-processor-invalid-rule.json");
+        for rule in [
+            r#"{"interval": {"start": 1, "step": 0}}"#,
+            r#"{"interval": {"start": 1, "step": -3}}"#,
+            r#"{"bins": [0, 65, 18]}"#,
+            r#"{"bins": [0, 18, 18]}"#,
+        ] {
+            let schema = format!(
+                r#"{{"columns": [{{"name": "age", "type": "int", "group_by": {}}}]}}"#,
+                rule
+            );
+            std::fs::write(&path, schema).unwrap();
+            let err = Ctx::from_schema_file(&path).err().unwrap();
+            assert!(matches!(err, Error::Schema(_)), "{}", rule);
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,131 @@
+use std::process::{Command, Output};
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_generic-table-processor"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stdout(args: &[&str]) -> String {
+    let output = run(args);
+    assert!(output.status.success(), "{:?}", output);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn fold_groups() {
+    let args = [
+        "-g",
+        "userid",
+        "fold",
+        "--op",
+        "sum",
+        "--column",
+        "a",
+        "test-cases/base.csv",
+    ];
+    assert_eq!(stdout(&args), "userid,SUM(a)\n0,5\n2,8\n");
+    let args = [
+        "-g",
+        "userid",
+        "-p",
+        "2",
+        "fold",
+        "--op",
+        "avg",
+        "--column",
+        "a",
+        "test-cases/base.csv",
+    ];
+    assert_eq!(stdout(&args), "userid,AVG(a)\n0,2.50\n2,4.00\n");
+    assert_eq!(
+        stdout(&["group", "-g", "userid", "test-cases/base.csv"]),
+        "userid,COUNT\n0,2\n2,2\n"
+    );
+    assert_eq!(
+        stdout(&["group", "-g", "userid,time", "test-cases/base.csv"]),
+        "time,userid,COUNT\n0,0,1\n0,2,2\n1,0,1\n"
+    );
+}
+
+#[test]
+fn filter_records() {
+    assert_eq!(
+        stdout(&[
+            "filter",
+            "--column",
+            "a",
+            "--gt",
+            "3",
+            "test-cases/base.csv"
+        ]),
+        "userid,time,a,b,c\n0,1,4,5,6\n2,0,7,8,9\n"
+    );
+    // the value is parsed as the type of the column
+    let output = run(&[
+        "filter",
+        "--column",
+        "a",
+        "--eq",
+        "x",
+        "test-cases/base.csv",
+    ]);
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn join_tables() {
+    let joined = stdout(&[
+        "join",
+        "--on",
+        "userid",
+        "test-cases/base.csv",
+        "test-cases/base.csv",
+    ]);
+    let mut lines = joined.lines();
+    assert_eq!(
+        lines.next(),
+        Some("userid,time,a,b,c,time_right,a_right,b_right,c_right")
+    );
+    // each user has two records, joined with both of them
+    assert_eq!(lines.count(), 8);
+}
+
+#[test]
+fn invalid_arguments() {
+    let output = run(&["fold", "--op", "avg", "test-cases/base.csv"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("`--column'"));
+    assert_eq!(run(&["fold", "--op", "nope"]).status.code(), Some(2));
+}
+
+#[test]
+fn error_messages() {
+    let stderr = |args: &[&str]| -> String {
+        let output = run(args);
+        assert_eq!(output.status.code(), Some(1));
+        String::from_utf8(output.stderr).unwrap()
+    };
+    assert_eq!(
+        stderr(&["-g", "zz", "group", "test-cases/base.csv"]),
+        "Error: key attribute is not found\n"
+    );
+    assert_eq!(
+        stderr(&[
+            "filter",
+            "--column",
+            "zz",
+            "--eq",
+            "1",
+            "test-cases/base.csv"
+        ]),
+        "Error: header `zz' is not found in context info\n"
+    );
+    assert_eq!(
+        stderr(&["fold", "--op", "avg", "test-cases/base.csv"]),
+        "Error: `--op avg' needs `--column'\n"
+    );
+    assert!(stderr(&["test-cases/missing.csv"])
+        .starts_with("Error: can't open `test-cases/missing.csv': "));
+}